@@ -0,0 +1,155 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use serde::{ Deserialize, Serialize };
+
+use web3::types::{ Address, H256, U64 };
+
+use crate::error::{ DeployError, DeployResult };
+
+// Writes to a sibling temporary file first and renames it over `path`, so an
+// interrupted run never leaves a truncated file behind. `error` picks the error kind.
+pub fn write_atomically(path: &str, data: &[u8], error: fn(String) -> DeployError) -> DeployResult<()> {
+
+    let tmp_path = format!("{}.tmp", path);
+    let mut file = std::fs::File::create(&tmp_path).map_err(|e| error(format!("failed to create {}: {}", tmp_path, e)))?;
+    file.write_all(data).map_err(|e| error(format!("failed to write {}: {}", tmp_path, e)))?;
+    file.sync_all().map_err(|e| error(format!("failed to sync {}: {}", tmp_path, e)))?;
+
+    std::fs::rename(&tmp_path, path).map_err(|e| error(format!("failed to replace {}: {}", path, e)))
+}
+
+#[derive(Clone, Debug)]
+#[derive(Deserialize, Serialize)]
+pub struct ContractRecord {
+    pub name: String,
+    pub address: Address,
+    pub tx_hash: H256,
+    pub block: Option<U64>,
+    pub deployer: Address,
+    pub artifact_hash: H256,
+}
+
+#[derive(Clone, Debug)]
+#[derive(Deserialize, Serialize)]
+pub struct CallRecord {
+    pub name: String,
+    pub tx_hash: H256,
+    pub block: Option<U64>,
+    pub sender: Address,
+}
+
+#[derive(Clone, Debug, Default)]
+#[derive(Deserialize, Serialize)]
+pub struct AddressBook {
+    #[serde(skip)]
+    path: String,
+    #[serde(default)]
+    pub contracts: BTreeMap<String,ContractRecord>,
+    #[serde(default)]
+    pub calls: BTreeMap<String,CallRecord>,
+}
+
+impl AddressBook {
+    pub fn load(path: &str) -> DeployResult<AddressBook> {
+
+        if !std::path::Path::new(path).exists() {
+            println!("Address book {} not found, starting a new one", path);
+            return Ok(AddressBook { path: path.to_string(), ..AddressBook::default() });
+        }
+
+        let file = std::fs::File::open(path).map_err(|e| DeployError::address_book(format!("failed to open {}: {}", path, e)))?;
+        let reader = std::io::BufReader::new(file);
+        let mut book: AddressBook =
+            serde_json::from_reader(reader).map_err(|e| DeployError::address_book(format!("{} is malformed: {}", path, e)))?;
+        book.path = path.to_string();
+        Ok(book)
+    }
+    pub fn save(&self) -> DeployResult<()> {
+        let data: Vec<u8> = serde_json::to_vec_pretty(self).map_err(|e| DeployError::address_book(format!("failed to serialize: {}", e)))?;
+        write_atomically(&self.path, &data, DeployError::address_book)
+    }
+    pub fn address(&self, name: &str) -> Option<Address> {
+        self.contracts.get(name).map(|r| r.address)
+    }
+    pub fn record_contract(&mut self, record: ContractRecord) -> DeployResult<()> {
+        self.contracts.insert(record.name.clone(), record);
+        self.save()
+    }
+    pub fn record_call(&mut self, record: CallRecord) -> DeployResult<()> {
+        self.calls.insert(record.name.clone(), record);
+        self.save()
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    pub fn address_book(contracts: &[(&str, Address)]) -> AddressBook {
+        let mut book: AddressBook = AddressBook::default();
+        for (name, address) in contracts.iter() {
+            book.contracts.insert(name.to_string(), ContractRecord {
+                name: name.to_string(),
+                address: *address,
+                tx_hash: H256::zero(),
+                block: None,
+                deployer: Address::zero(),
+                artifact_hash: H256::zero(),
+            });
+        }
+        book
+    }
+
+    fn temp_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("address-book-test-{}-{}.json", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn round_trips_records_through_the_file() {
+        let path: String = temp_path("round-trip");
+        let mut book: AddressBook = AddressBook::load(&path).unwrap();
+        assert!(book.contracts.is_empty() && book.calls.is_empty());
+
+        book.record_contract(ContractRecord {
+            name: "weth9".to_string(),
+            address: Address::repeat_byte(0x11),
+            tx_hash: H256::repeat_byte(0x22),
+            block: Some(U64::from(7)),
+            deployer: Address::repeat_byte(0x33),
+            artifact_hash: H256::repeat_byte(0x44),
+        }).unwrap();
+        book.record_call(CallRecord {
+            name: "set_fee_to".to_string(),
+            tx_hash: H256::repeat_byte(0x55),
+            block: None,
+            sender: Address::repeat_byte(0x33),
+        }).unwrap();
+        assert!(!std::path::Path::new(&format!("{}.tmp", path)).exists());
+
+        let mut loaded: AddressBook = AddressBook::load(&path).unwrap();
+        assert_eq!(loaded.address("weth9"), Some(Address::repeat_byte(0x11)));
+        assert_eq!(loaded.address("uni"), None);
+        let record: ContractRecord = loaded.contracts["weth9"].clone();
+        assert_eq!((record.tx_hash, record.block, record.deployer, record.artifact_hash), (H256::repeat_byte(0x22), Some(U64::from(7)), Address::repeat_byte(0x33), H256::repeat_byte(0x44)));
+        let call: &CallRecord = &loaded.calls["set_fee_to"];
+        assert_eq!((call.tx_hash, call.block, call.sender), (H256::repeat_byte(0x55), None, Address::repeat_byte(0x33)));
+
+        // records written by the loaded book keep the earlier ones
+        loaded.record_contract(ContractRecord { name: "uni".to_string(), ..record }).unwrap();
+        let reloaded: AddressBook = AddressBook::load(&path).unwrap();
+        assert_eq!(reloaded.contracts.len(), 2);
+        assert_eq!(reloaded.calls.len(), 1);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rejects_a_malformed_book() {
+        let path: String = temp_path("malformed");
+        std::fs::write(&path, "{ \"contracts\": [").unwrap();
+        let error: DeployError = AddressBook::load(&path).err().unwrap();
+        assert!(matches!(error.kind, crate::error::ErrorKind::AddressBook(_)), "{}", error);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
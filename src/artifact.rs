@@ -0,0 +1,356 @@
+use std::collections::{ BTreeMap, HashMap };
+use std::fmt;
+use serde::{ Deserialize };
+use serde_json::Value;
+
+use web3::types::{ Address, H256 };
+use web3::contract::Contract;
+use web3::signing::keccak256;
+
+use crate::config::ContractPaths;
+use crate::error::{ DeployError, DeployResult };
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ArtifactFormat {
+    Solc,
+    Truffle,
+    Vyper,
+}
+
+impl fmt::Display for ArtifactFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArtifactFormat::Solc    => write!(f, "solc"),
+            ArtifactFormat::Truffle => write!(f, "Truffle"),
+            ArtifactFormat::Vyper   => write!(f, "Vyper"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+#[derive(Deserialize)]
+pub struct LinkRange {
+    pub start: usize,
+    pub length: usize,
+}
+
+// source file -> library name -> byte ranges of the placeholders in the bytecode
+pub type LinkReferences = BTreeMap<String,BTreeMap<String,Vec<LinkRange>>>;
+
+#[derive(Clone, Debug)]
+pub struct Artifact {
+    pub name: String,
+    pub path: String,
+    pub format: ArtifactFormat,
+    pub abi: web3::ethabi::Contract,
+    pub artifact_hash: H256,
+    creation_code: String,
+    runtime_code: String,
+    pub link_references: LinkReferences,
+    pub runtime_link_references: LinkReferences,
+    pub immutable_references: Vec<LinkRange>,
+}
+
+fn hex_object(value: &Value) -> String {
+    value.as_str()
+        .unwrap_or("")
+        .trim_start_matches("0x")
+        .to_string()
+}
+
+fn detect_format(json: &Value) -> Option<ArtifactFormat> {
+    if json["evm"]["bytecode"].is_object() {
+        // Vyper output carries neither solc metadata nor link references.
+        if json["metadata"].is_null() && json["evm"]["bytecode"]["linkReferences"].is_null() {
+            Some(ArtifactFormat::Vyper)
+        } else {
+            Some(ArtifactFormat::Solc)
+        }
+    } else if json["bytecode"].is_string() && json["deployedBytecode"].is_string() {
+        Some(ArtifactFormat::Truffle)
+    } else {
+        None
+    }
+}
+
+impl Artifact {
+    pub fn load(name: &str, path: &str) -> DeployResult<Artifact> {
+        let data: Vec<u8> =
+            std::fs::read(path).map_err(|e| DeployError::artifact(format!("failed to read: {} ({})", e, path)).for_contract(name))?;
+        Artifact::parse(name, path, &data)
+    }
+    fn parse(name: &str, path: &str, data: &[u8]) -> DeployResult<Artifact> {
+
+        let fail = |message: String| DeployError::artifact(format!("{} ({})", message, path)).for_contract(name);
+
+        let json: Value = serde_json::from_slice(data).map_err(|e| fail(format!("not valid JSON: {}", e)))?;
+
+        let format: ArtifactFormat =
+            detect_format(&json)
+                .ok_or_else(|| fail("neither solc, Truffle nor Vyper output".to_string()))?;
+
+        let abi_data: Vec<u8> = serde_json::to_vec(&json["abi"]).map_err(|e| fail(format!("unreadable ABI: {}", e)))?;
+        let abi = web3::ethabi::Contract::load(abi_data.as_slice()).map_err(|e| fail(format!("invalid ABI: {}", e)))?;
+
+        let (creation_code, runtime_code, link_references, runtime_link_references, immutable_references): (String, String, &Value, &Value, &Value) =
+            match format {
+                ArtifactFormat::Solc => (
+                    hex_object(&json["evm"]["bytecode"]["object"]),
+                    hex_object(&json["evm"]["deployedBytecode"]["object"]),
+                    &json["evm"]["bytecode"]["linkReferences"],
+                    &json["evm"]["deployedBytecode"]["linkReferences"],
+                    &json["evm"]["deployedBytecode"]["immutableReferences"],
+                ),
+                ArtifactFormat::Truffle => (
+                    hex_object(&json["bytecode"]),
+                    hex_object(&json["deployedBytecode"]),
+                    &json["linkReferences"],
+                    &json["deployedLinkReferences"],
+                    &json["immutableReferences"],
+                ),
+                ArtifactFormat::Vyper => (
+                    hex_object(&json["evm"]["bytecode"]["object"]),
+                    hex_object(&json["evm"]["deployedBytecode"]["object"]),
+                    &Value::Null,
+                    &Value::Null,
+                    &Value::Null,
+                ),
+            };
+
+        let parse_link_references = |references: &Value| -> DeployResult<LinkReferences> {
+            if references.is_object() {
+                serde_json::from_value(references.clone())
+                    .map_err(|e| fail(format!("malformed linkReferences: {}", e)))
+            } else {
+                Ok(LinkReferences::new())
+            }
+        };
+        let link_references: LinkReferences = parse_link_references(link_references)?;
+        let runtime_link_references: LinkReferences = parse_link_references(runtime_link_references)?;
+        let immutable_references: Vec<LinkRange> =
+            if immutable_references.is_object() {
+                serde_json::from_value::<BTreeMap<String,Vec<LinkRange>>>(immutable_references.clone())
+                    .map_err(|e| fail(format!("malformed immutableReferences: {}", e)))?
+                    .into_values()
+                    .flatten()
+                    .collect()
+            } else {
+                Vec::new()
+            };
+
+        Ok(Artifact {
+            name: name.to_string(),
+            path: path.to_string(),
+            format,
+            abi,
+            artifact_hash: H256::from(keccak256(data)),
+            creation_code,
+            runtime_code,
+            link_references,
+            runtime_link_references,
+            immutable_references,
+        })
+    }
+    pub fn library_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> =
+            self.link_references
+                .values()
+                .flat_map(|libraries| libraries.keys())
+                .map(|name| name.as_str())
+                .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+    // Splices library addresses over the `__$...$__` placeholders listed in linkReferences.
+    fn error(&self, message: String) -> DeployError {
+        DeployError::artifact(format!("{} ({} format, {})", message, self.format, self.path)).for_contract(&self.name)
+    }
+    pub fn linked_bytecode(&self, libraries: &HashMap<String,Address>) -> DeployResult<Vec<u8>> {
+
+        if self.creation_code.is_empty() {
+            return Err(self.error("no creation bytecode, is it an interface or abstract contract?".to_string()));
+        }
+
+        let mut code: String = self.creation_code.clone();
+        for (source, references) in self.link_references.iter() {
+            for (library, ranges) in references.iter() {
+                let address: &Address =
+                    libraries.get(library)
+                        .ok_or_else(|| self.error(format!("library {}:{} is not deployed", source, library)))?;
+                let address_hex: String = hex::encode(address.as_bytes());
+                for range in ranges.iter() {
+                    if range.length != 20 || (range.start + range.length) * 2 > code.len() {
+                        return Err(self.error(format!("invalid link reference for {} at byte {}", library, range.start)));
+                    }
+                    code.replace_range(range.start * 2..(range.start + range.length) * 2, &address_hex);
+                }
+            }
+        }
+
+        if code.contains("__") {
+            return Err(self.error("library placeholders that are not listed in linkReferences".to_string()));
+        }
+        hex::decode(&code).map_err(|e| self.error(format!("invalid creation bytecode: {}", e)))
+    }
+    // Library placeholders in the runtime code are zero-filled; `masked_ranges` covers them.
+    pub fn runtime_bytecode(&self) -> Option<Vec<u8>> {
+        if self.runtime_code.is_empty() {
+            return None;
+        }
+        let mut code: String = self.runtime_code.clone();
+        for range in self.runtime_link_references.values().flat_map(|r| r.values()).flatten() {
+            if (range.start + range.length) * 2 <= code.len() {
+                code.replace_range(range.start * 2..(range.start + range.length) * 2, &"0".repeat(range.length * 2));
+            }
+        }
+        hex::decode(&code).ok()
+    }
+    pub fn masked_ranges(&self) -> Vec<LinkRange> {
+        self.immutable_references
+            .iter()
+            .chain(self.runtime_link_references.values().flat_map(|r| r.values()).flatten())
+            .copied()
+            .collect()
+    }
+    pub fn at(&self, web3: &web3::Web3<web3::transports::Http>, address: Address) -> Contract<web3::transports::Http> {
+        Contract::new(web3.eth(), address, self.abi.clone())
+    }
+}
+
+pub struct ArtifactRegistry {
+    artifacts: HashMap<String,Artifact>,
+}
+
+impl ArtifactRegistry {
+    pub fn load(paths: &ContractPaths, library_paths: &HashMap<String,String>) -> DeployResult<ArtifactRegistry> {
+        let mut artifacts: HashMap<String,Artifact> = HashMap::new();
+        for (name, path) in paths.entries() {
+            artifacts.insert(name.to_string(), Artifact::load(name, path)?);
+        }
+        for (name, path) in library_paths.iter() {
+            if artifacts.contains_key(name) {
+                return Err(DeployError::config(format!("library '{}' has the same name as a contract artifact", name)));
+            }
+            artifacts.insert(name.clone(), Artifact::load(name, path)?);
+        }
+        Ok(ArtifactRegistry { artifacts })
+    }
+    pub fn get(&self, name: &str) -> DeployResult<&Artifact> {
+        self.artifacts.get(name)
+            .ok_or_else(|| DeployError::artifact(format!("unknown artifact '{}'", name)))
+    }
+    pub fn contract(&self, web3: &web3::Web3<web3::transports::Http>, name: &str, address: Address) -> DeployResult<Contract<web3::transports::Http>> {
+        self.get(name).map(|artifact| artifact.at(web3, address))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::str::FromStr;
+    use serde_json::json;
+    use super::*;
+
+    const PLACEHOLDER: &str = "__$0123456789abcdef0123456789abcdef01$__";
+
+    pub fn artifact(json: Value) -> Artifact {
+        Artifact::parse("test", "test.json", &serde_json::to_vec(&json).unwrap()).unwrap()
+    }
+
+    pub fn registry(artifacts: Vec<(&str, Value)>) -> ArtifactRegistry {
+        ArtifactRegistry { artifacts: artifacts.into_iter().map(|(name, json)| (name.to_string(), artifact(json))).collect() }
+    }
+
+    pub fn linking_artifact(library: &str) -> Value {
+        json!({
+            "abi": [],
+            "metadata": "{}",
+            "evm": {
+                "bytecode": { "object": format!("6080{}", PLACEHOLDER), "linkReferences": { "contracts/Lib.sol": { library: [ { "start": 2, "length": 20 } ] } } },
+                "deployedBytecode": { "object": "" },
+            },
+        })
+    }
+
+    fn link_references(start: usize) -> Value {
+        json!({ "contracts/Math.sol": { "Math": [ { "start": start, "length": 20 } ] } })
+    }
+
+    #[test]
+    fn detects_the_artifact_format() {
+        let solc = json!({ "abi": [], "metadata": "{}", "evm": { "bytecode": { "object": "6080", "linkReferences": {} }, "deployedBytecode": { "object": "6080" } } });
+        let vyper = json!({ "abi": [], "evm": { "bytecode": { "object": "0x6080" }, "deployedBytecode": { "object": "0x6080" } } });
+        let truffle = json!({ "abi": [], "bytecode": "0x6080", "deployedBytecode": "0x6080" });
+        assert_eq!(detect_format(&solc), Some(ArtifactFormat::Solc));
+        assert_eq!(detect_format(&vyper), Some(ArtifactFormat::Vyper));
+        assert_eq!(detect_format(&truffle), Some(ArtifactFormat::Truffle));
+        assert_eq!(detect_format(&json!({ "abi": [] })), None);
+        assert_eq!(detect_format(&json!({ "abi": [], "bytecode": "0x6080" })), None);
+    }
+
+    #[test]
+    fn links_library_addresses_over_placeholders() {
+        let artifact = artifact(json!({
+            "abi": [],
+            "metadata": "{}",
+            "evm": {
+                "bytecode": { "object": format!("6080{}5050", PLACEHOLDER), "linkReferences": link_references(2) },
+                "deployedBytecode": { "object": "" },
+            },
+        }));
+        assert_eq!(artifact.library_names(), vec!["Math"]);
+
+        let math: Address = Address::from_str("00000000000000000000000000000000000000aa").unwrap();
+        let libraries: HashMap<String,Address> = HashMap::from([("Math".to_string(), math)]);
+        let mut expected: Vec<u8> = vec![0x60, 0x80];
+        expected.extend_from_slice(math.as_bytes());
+        expected.extend_from_slice(&[0x50, 0x50]);
+        assert_eq!(artifact.linked_bytecode(&libraries).unwrap(), expected);
+
+        assert!(artifact.linked_bytecode(&HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn rejects_placeholders_missing_from_link_references() {
+        let artifact = artifact(json!({
+            "abi": [],
+            "metadata": "{}",
+            "evm": { "bytecode": { "object": format!("6080{}", PLACEHOLDER), "linkReferences": {} }, "deployedBytecode": { "object": "" } },
+        }));
+        assert!(artifact.linked_bytecode(&HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn rejects_link_references_outside_the_bytecode() {
+        let artifact = artifact(json!({
+            "abi": [],
+            "metadata": "{}",
+            "evm": { "bytecode": { "object": format!("6080{}", PLACEHOLDER), "linkReferences": link_references(10) }, "deployedBytecode": { "object": "" } },
+        }));
+        let libraries: HashMap<String,Address> = HashMap::from([("Math".to_string(), Address::zero())]);
+        assert!(artifact.linked_bytecode(&libraries).is_err());
+    }
+
+    #[test]
+    fn masks_immutables_and_runtime_library_references() {
+        let artifact = artifact(json!({
+            "abi": [],
+            "metadata": "{}",
+            "evm": {
+                "bytecode": { "object": "6080", "linkReferences": {} },
+                "deployedBytecode": {
+                    "object": format!("7f{}{}", "00".repeat(32), PLACEHOLDER),
+                    "linkReferences": link_references(33),
+                    "immutableReferences": { "12": [ { "start": 1, "length": 32 } ] },
+                },
+            },
+        }));
+        let ranges: Vec<(usize, usize)> = artifact.masked_ranges().iter().map(|r| (r.start, r.length)).collect();
+        assert_eq!(ranges, vec![(1, 32), (33, 20)]);
+
+        // the runtime placeholder comes back zero-filled
+        let runtime: Vec<u8> = artifact.runtime_bytecode().unwrap();
+        assert_eq!(runtime.len(), 53);
+        assert!(runtime[33..].iter().all(|b| *b == 0));
+    }
+}
@@ -0,0 +1,497 @@
+use std::str::FromStr;
+
+use web3::types::{ Address, H256, U256, U64 };
+
+use web3_tools::AsEip55;
+
+use crate::artifact::ArtifactRegistry;
+use crate::config::NetworkProfile;
+use crate::manifest::Manifest;
+use crate::liquidity::{ NeonswapEnvironment, apply_slippage };
+use crate::governance::{ EncodedAction, Governor, ProposalSpec, VoteReceipt, encode_action, read_proposal };
+use crate::timelock::{ TimelockClient, TimelockQueue };
+use crate::factory::FactoryClient;
+use crate::migration::Migration;
+use crate::v1_exchange::{ V1Exchange, V1Receipt };
+use crate::flash_swap::{ Borrow, FlashSwap };
+use crate::uni::{ Checkpoint, MintSchedule, UniClient, VotingSnapshot };
+use crate::signers::{ DEPLOYER, LIQUIDITY_PROVIDER, TRADER, VOTER };
+use crate::error::{ DeployError, DeployResult };
+
+const GOVERNANCE_USAGE: &str =
+    "governance propose --proposal <file> | vote --id <id> --support <for|against> | status --id <id> | receipt --id <id> [--voter <role|address>] | queue --id <id> | execute --id <id> | cancel --id <id>";
+
+const TIMELOCK_USAGE: &str =
+    "timelock status | queue --proposal <file> [--eta <timestamp>] | execute [--hash <hash>] | cancel --hash <hash> | set-pending-admin --admin <role|address> | accept-admin";
+
+const UNI_USAGE: &str =
+    "uni delegate --to <role|address> | mint --to <role|address> --amount <raw amount> | mint-schedule | votes [--account <role|address>] | checkpoints [--account <role|address>] | snapshot --block <number> [--output <file>]";
+
+const FACTORY_USAGE: &str =
+    "factory status | fees | set-fee-to --to <role|address|none> | set-fee-to-setter --to <role|address>";
+
+const MIGRATE_USAGE: &str =
+    "migrate [--token <name|address>] [--slippage-bps <bps>] [--deadline-secs <seconds>]";
+
+const FLASH_SWAP_USAGE: &str =
+    "flash-swap [--token <name|address>] [--borrow <weth|token>] [--amount <raw amount>] [--slippage-bps <bps>] [--provider <role>]";
+
+const V1_USAGE: &str =
+    "v1 prices [--amount <raw amount>] | buy --eth <wei>|--tokens <raw amount> | sell --tokens <raw amount>|--eth <wei> | swap --tokens <raw amount>|--bought <raw amount> --output <name|address>|--exchange <address> | remove --amount <liquidity>, all with [--token <name|address>] [--to <role|address>] [--slippage-bps <bps>] [--deadline-secs <seconds>]";
+
+pub fn arg_value(name: &str) -> Option<String> {
+    let args: Vec<String> = std::env::args().collect();
+    args.iter()
+        .position(|arg| arg == name)
+        .and_then(|i| args.get(i + 1))
+        .cloned()
+}
+
+// Every flag takes a value, so whatever is left after dropping `--flag value` pairs
+// and the binary is positional, wherever the flags were put.
+fn positional_args(args: &[String]) -> Vec<String> {
+    let mut positional: Vec<String> = Vec::new();
+    let mut rest = args.iter().skip(1);
+    while let Some(arg) = rest.next() {
+        if arg.starts_with("--") {
+            rest.next();
+        } else {
+            positional.push(arg.clone());
+        }
+    }
+    positional
+}
+
+fn positional_arg(index: usize) -> Option<String> {
+    let args: Vec<String> = std::env::args().collect();
+    positional_args(&args).into_iter().nth(index)
+}
+
+// `<binary> <command> <action> --flag value ...`, without a command the deployment runs.
+pub fn command_name() -> Option<String> {
+    positional_arg(0)
+}
+
+fn action_name() -> Option<String> {
+    positional_arg(1)
+}
+
+// migrate and flash-swap have no action, the other commands take exactly one.
+fn check_positional_args(command: &str, args: &[String]) -> DeployResult<()> {
+    let expected: usize = if matches!(command, "migrate" | "flash-swap") { 1 } else { 2 };
+    match positional_args(args).get(expected) {
+        Some(extra) => Err(DeployError::config(format!("unexpected argument '{}' for the {} command", extra, command))),
+        None => Ok(()),
+    }
+}
+
+fn required_arg(name: &str, usage: &str) -> DeployResult<String> {
+    arg_value(name).ok_or_else(|| DeployError::config(format!("missing {}, usage: {}", name, usage)))
+}
+
+fn uint_arg(name: &str, usage: &str) -> DeployResult<U256> {
+    let value: String = required_arg(name, usage)?;
+    U256::from_dec_str(&value).map_err(|_| DeployError::config(format!("{} '{}' is not a decimal integer", name, value)))
+}
+
+fn u64_arg(name: &str, usage: &str) -> DeployResult<u64> {
+    let value: U256 = uint_arg(name, usage)?;
+    if value > U256::from(u64::MAX) {
+        return Err(DeployError::config(format!("{} {} is larger than {}, usage: {}", name, value, u64::MAX, usage)));
+    }
+    Ok(value.as_u64())
+}
+
+fn hash_arg(name: &str, usage: &str) -> DeployResult<H256> {
+    let value: String = required_arg(name, usage)?;
+    H256::from_str(&value).map_err(|_| DeployError::config(format!("{} '{}' is not a 32 byte hash", name, value)))
+}
+
+// The role given with --role, or `default` if that role is configured, or the deployer.
+fn role_arg(env: &NeonswapEnvironment, default: &str) -> String {
+    arg_value("--role").unwrap_or_else(|| if env.signers.contains(default) { default.to_string() } else { DEPLOYER.to_string() })
+}
+
+// An address book name or a plain address.
+fn contract_value(env: &NeonswapEnvironment, name: &str, value: &str) -> DeployResult<Address> {
+    if value.starts_with("0x") {
+        Address::from_str(value).map_err(|_| DeployError::config(format!("{} '{}' is not an address", name, value)))
+    } else {
+        env.contract_address(value)
+    }
+}
+
+fn contract_arg(env: &NeonswapEnvironment, name: &str, default: &str) -> DeployResult<Address> {
+    contract_value(env, name, &arg_value(name).unwrap_or_else(|| default.to_string()))
+}
+
+fn required_contract_arg(env: &NeonswapEnvironment, name: &str, usage: &str) -> DeployResult<Address> {
+    contract_value(env, name, &required_arg(name, usage)?)
+}
+
+fn optional_u64_arg(name: &str, default: u64) -> DeployResult<u64> {
+    match arg_value(name) {
+        Some(value) => value.parse().map_err(|_| DeployError::config(format!("{} '{}' is not a whole number", name, value))),
+        None => Ok(default),
+    }
+}
+
+// Accepts either a role name or a plain address.
+fn account_arg(env: &NeonswapEnvironment, name: &str, default: &str) -> DeployResult<Address> {
+    match arg_value(name) {
+        Some(value) if value.starts_with("0x") => Address::from_str(&value).map_err(|_| DeployError::config(format!("{} '{}' is not an address", name, value))),
+        Some(role) => Ok(env.signers.get(&role)?.address),
+        None => Ok(env.signers.get(default)?.address),
+    }
+}
+
+pub async fn run_command(command: &str, env: &NeonswapEnvironment, profile: &NetworkProfile, manifest: &Manifest, artifacts: &ArtifactRegistry) -> DeployResult<()> {
+    let args: Vec<String> = std::env::args().collect();
+    check_positional_args(command, &args)?;
+    match command {
+        "governance" => governance(env, manifest, artifacts).await,
+        "timelock" => timelock(env, profile, manifest, artifacts).await,
+        "uni" => uni(env, artifacts).await,
+        "factory" => factory(env, artifacts).await,
+        "migrate" => migrate(env, artifacts).await,
+        "v1" => v1(env, artifacts).await,
+        "flash-swap" => flash_swap(env, artifacts).await,
+        _ => Err(DeployError::config(format!("unknown command '{}', known commands: governance, timelock, uni, factory, migrate, v1, flash-swap", command))),
+    }
+}
+
+async fn governance(env: &NeonswapEnvironment, manifest: &Manifest, artifacts: &ArtifactRegistry) -> DeployResult<()> {
+
+    let governor: Governor = Governor::new(env, artifacts)?;
+    let action: String = action_name().ok_or_else(|| DeployError::config(format!("usage: {}", GOVERNANCE_USAGE)))?;
+
+    let id: U256 =
+        match action.as_str() {
+            "propose" => {
+                let spec: ProposalSpec = read_proposal(&required_arg("--proposal", GOVERNANCE_USAGE)?)?;
+                println!("Proposing '{}'", spec.description);
+                let id: U256 = governor.propose(&role_arg(env, DEPLOYER), &spec, manifest, artifacts).await?;
+                println!("Proposal {} created", id);
+                id
+            },
+            "vote" => {
+                let id: U256 = uint_arg("--id", GOVERNANCE_USAGE)?;
+                let support: bool =
+                    match required_arg("--support", GOVERNANCE_USAGE)?.as_str() {
+                        "for" | "yes" => true,
+                        "against" | "no" => false,
+                        other => return Err(DeployError::config(format!("--support '{}' must be for or against", other))),
+                    };
+                governor.cast_vote(&role_arg(env, VOTER), id, support).await?;
+                id
+            },
+            "receipt" => {
+                let id: U256 = uint_arg("--id", GOVERNANCE_USAGE)?;
+                let voter: Address = account_arg(env, "--voter", &role_arg(env, VOTER))?;
+                let receipt: VoteReceipt = governor.receipt(id, voter).await?;
+                match receipt.has_voted {
+                    true => println!("{} voted {} proposal {} with {} votes", voter.as_eip55(), if receipt.support { "for" } else { "against" }, id, receipt.votes),
+                    false => println!("{} has not voted on proposal {}", voter.as_eip55(), id),
+                }
+                id
+            },
+            "status" => uint_arg("--id", GOVERNANCE_USAGE)?,
+            "queue" => {
+                let id: U256 = uint_arg("--id", GOVERNANCE_USAGE)?;
+                governor.queue(&role_arg(env, DEPLOYER), id).await?;
+                id
+            },
+            "execute" => {
+                let id: U256 = uint_arg("--id", GOVERNANCE_USAGE)?;
+                governor.execute(&role_arg(env, DEPLOYER), id).await?;
+                id
+            },
+            "cancel" => {
+                let id: U256 = uint_arg("--id", GOVERNANCE_USAGE)?;
+                governor.cancel(&role_arg(env, DEPLOYER), id).await?;
+                id
+            },
+            other => return Err(DeployError::config(format!("unknown governance action '{}', usage: {}", other, GOVERNANCE_USAGE))),
+        };
+
+    governor.status(id).await?.report();
+    Ok(())
+}
+
+async fn timelock(env: &NeonswapEnvironment, profile: &NetworkProfile, manifest: &Manifest, artifacts: &ArtifactRegistry) -> DeployResult<()> {
+
+    let timelock: TimelockClient = TimelockClient::new(env, artifacts)?;
+    let mut queue: TimelockQueue = TimelockQueue::load(&profile.timelock_queue_path)?;
+    let action: String = action_name().ok_or_else(|| DeployError::config(format!("usage: {}", TIMELOCK_USAGE)))?;
+    let role: String = role_arg(env, DEPLOYER);
+
+    match action.as_str() {
+        "status" => {},
+        "queue" => {
+            let spec: ProposalSpec = read_proposal(&required_arg("--proposal", TIMELOCK_USAGE)?)?;
+            let eta: Option<U256> = if arg_value("--eta").is_some() { Some(uint_arg("--eta", TIMELOCK_USAGE)?) } else { None };
+            for action in spec.actions.iter() {
+                let encoded: EncodedAction = encode_action(action, manifest, artifacts, &env.address_book, env.signers.deployer().address)?;
+                let hash: H256 = timelock.queue(&role, &mut queue, &encoded, eta).await?;
+                println!("Queued {} as {:?}", encoded.signature, hash);
+            }
+        },
+        "execute" => {
+            if arg_value("--hash").is_some() {
+                timelock.execute(&role, &mut queue, hash_arg("--hash", TIMELOCK_USAGE)?).await?;
+            } else {
+                let executed: usize = timelock.execute_ready(&role, &mut queue).await?;
+                println!("Executed {} ready transactions", executed);
+            }
+        },
+        "cancel" => timelock.cancel(&role, &mut queue, hash_arg("--hash", TIMELOCK_USAGE)?).await?,
+        "set-pending-admin" => {
+            required_arg("--admin", TIMELOCK_USAGE)?;
+            let pending_admin: Address = account_arg(env, "--admin", DEPLOYER)?;
+            timelock.set_pending_admin(&role, pending_admin).await?;
+        },
+        "accept-admin" => timelock.accept_admin(&role).await?,
+        other => return Err(DeployError::config(format!("unknown timelock action '{}', usage: {}", other, TIMELOCK_USAGE))),
+    }
+
+    timelock.report(&queue).await
+}
+
+async fn uni(env: &NeonswapEnvironment, artifacts: &ArtifactRegistry) -> DeployResult<()> {
+
+    let uni: UniClient = UniClient::new(env, artifacts)?;
+    let action: String = action_name().ok_or_else(|| DeployError::config(format!("usage: {}", UNI_USAGE)))?;
+    let role: String = role_arg(env, DEPLOYER);
+
+    match action.as_str() {
+        "delegate" => {
+            required_arg("--to", UNI_USAGE)?;
+            let delegatee: Address = account_arg(env, "--to", DEPLOYER)?;
+            uni.delegate(&role, delegatee).await?;
+            println!("{} delegated its votes to {}", role, delegatee.as_eip55());
+        },
+        "mint" => {
+            required_arg("--to", UNI_USAGE)?;
+            let to: Address = account_arg(env, "--to", DEPLOYER)?;
+            let amount: U256 = uint_arg("--amount", UNI_USAGE)?;
+            let schedule: MintSchedule = uni.mint(&role, to, amount).await?;
+            println!("Minted {} UNI to {}", amount, to.as_eip55());
+            schedule.report();
+        },
+        "mint-schedule" => uni.mint_schedule().await?.report(),
+        "votes" => {
+            let account: Address = account_arg(env, "--account", &role)?;
+            println!("{} delegates to {}", account.as_eip55(), uni.delegates(account).await?.as_eip55());
+            println!("{} currently has {} votes", account.as_eip55(), uni.current_votes(account).await?);
+        },
+        "checkpoints" => {
+            let account: Address = account_arg(env, "--account", &role)?;
+            let checkpoints: Vec<Checkpoint> = uni.checkpoints(account).await?;
+            println!("{} checkpoints for {}", checkpoints.len(), account.as_eip55());
+            for (index, checkpoint) in checkpoints.iter().enumerate() {
+                println!("    #{} from block {}: {} votes", index, checkpoint.from_block, checkpoint.votes);
+            }
+        },
+        "snapshot" => {
+            let block: U64 = U64::from(u64_arg("--block", UNI_USAGE)?);
+            let snapshot: VotingSnapshot = uni.snapshot(block).await?;
+            snapshot.report();
+            if let Some(path) = arg_value("--output") {
+                snapshot.export(&path)?;
+                println!("Snapshot written to {}", path);
+            }
+        },
+        other => return Err(DeployError::config(format!("unknown uni action '{}', usage: {}", other, UNI_USAGE))),
+    }
+    Ok(())
+}
+
+async fn factory(env: &NeonswapEnvironment, artifacts: &ArtifactRegistry) -> DeployResult<()> {
+
+    let factory: FactoryClient = FactoryClient::new(env, artifacts)?;
+    let action: String = action_name().ok_or_else(|| DeployError::config(format!("usage: {}", FACTORY_USAGE)))?;
+    let role: String = role_arg(env, DEPLOYER);
+
+    match action.as_str() {
+        "status" => {},
+        "fees" => return factory.report_fees().await,
+        "set-fee-to" => {
+            // `none` switches the protocol fee off
+            let fee_to: Address =
+                match required_arg("--to", FACTORY_USAGE)?.as_str() {
+                    "none" => Address::zero(),
+                    _ => account_arg(env, "--to", DEPLOYER)?,
+                };
+            factory.set_fee_to(&role, fee_to).await?;
+        },
+        "set-fee-to-setter" => {
+            required_arg("--to", FACTORY_USAGE)?;
+            factory.set_fee_to_setter(&role, account_arg(env, "--to", DEPLOYER)?).await?;
+        },
+        other => return Err(DeployError::config(format!("unknown factory action '{}', usage: {}", other, FACTORY_USAGE))),
+    }
+    factory.report().await
+}
+
+async fn migrate(env: &NeonswapEnvironment, artifacts: &ArtifactRegistry) -> DeployResult<()> {
+
+    let token: Address = contract_arg(env, "--token", "weth_partner")?;
+    let slippage_bps: u64 = optional_u64_arg("--slippage-bps", 50)?;
+    let deadline_secs: u64 = optional_u64_arg("--deadline-secs", 1200)?;
+    if slippage_bps > 10_000 {
+        return Err(DeployError::config(format!("--slippage-bps {} is more than 100%, usage: {}", slippage_bps, MIGRATE_USAGE)));
+    }
+
+    let migration: Migration = Migration::new(env, artifacts, token)?;
+    migration.migrate(&role_arg(env, LIQUIDITY_PROVIDER), slippage_bps, deadline_secs).await?.report();
+    Ok(())
+}
+
+fn plus_slippage(amount: U256, slippage_bps: u64) -> U256 {
+    amount * (10_000 + slippage_bps) / 10_000
+}
+
+async fn v1(env: &NeonswapEnvironment, artifacts: &ArtifactRegistry) -> DeployResult<()> {
+
+    let exchange: V1Exchange = V1Exchange::for_token(env, artifacts, contract_arg(env, "--token", "weth_partner")?).await?;
+    let action: String = action_name().ok_or_else(|| DeployError::config(format!("usage: {}", V1_USAGE)))?;
+    let role: String = role_arg(env, LIQUIDITY_PROVIDER);
+    let slippage_bps: u64 = optional_u64_arg("--slippage-bps", 50)?;
+    if slippage_bps > 10_000 {
+        return Err(DeployError::config(format!("--slippage-bps {} is more than 100%, usage: {}", slippage_bps, V1_USAGE)));
+    }
+    let deadline: U256 = exchange.deadline(optional_u64_arg("--deadline-secs", 1200)?).await?;
+    let recipient: Option<Address> = if arg_value("--to").is_some() { Some(account_arg(env, "--to", &role)?) } else { None };
+
+    let receipt: V1Receipt =
+        match action.as_str() {
+            "prices" => {
+                let amount: U256 = if arg_value("--amount").is_some() { uint_arg("--amount", V1_USAGE)? } else { U256::exp10(18) };
+                let (eth_reserve, token_reserve) = exchange.reserves().await?;
+                println!("V1 exchange {} for {}", exchange.address().as_eip55(), exchange.token_address().as_eip55());
+                println!("    reserves {} wei ETH and {} tokens, {} liquidity", eth_reserve, token_reserve, exchange.total_supply().await?);
+                println!("    {} holds {} of it", role, exchange.liquidity_balance(env.signers.get(&role)?.address).await?);
+                println!("    selling {} wei ETH buys {} tokens", amount, exchange.get_eth_to_token_input_price(amount).await?);
+                println!("    buying {} tokens costs {} wei ETH", amount, exchange.get_eth_to_token_output_price(amount).await?);
+                println!("    selling {} tokens buys {} wei ETH", amount, exchange.get_token_to_eth_input_price(amount).await?);
+                println!("    buying {} wei ETH costs {} tokens", amount, exchange.get_token_to_eth_output_price(amount).await?);
+                return Ok(());
+            },
+            "buy" if arg_value("--eth").is_some() => {
+                let eth_sold: U256 = uint_arg("--eth", V1_USAGE)?;
+                let min_tokens: U256 = apply_slippage(exchange.get_eth_to_token_input_price(eth_sold).await?, slippage_bps)?;
+                exchange.eth_to_token_input(&role, eth_sold, min_tokens, deadline, recipient).await?
+            },
+            "buy" => {
+                let tokens_bought: U256 = uint_arg("--tokens", V1_USAGE)?;
+                let max_eth: U256 = plus_slippage(exchange.get_eth_to_token_output_price(tokens_bought).await?, slippage_bps);
+                exchange.eth_to_token_output(&role, tokens_bought, max_eth, deadline, recipient).await?
+            },
+            "sell" if arg_value("--tokens").is_some() => {
+                let tokens_sold: U256 = uint_arg("--tokens", V1_USAGE)?;
+                let min_eth: U256 = apply_slippage(exchange.get_token_to_eth_input_price(tokens_sold).await?, slippage_bps)?;
+                exchange.approve_token(&role, tokens_sold).await?;
+                exchange.token_to_eth_input(&role, tokens_sold, min_eth, deadline, recipient).await?
+            },
+            "sell" => {
+                let eth_bought: U256 = uint_arg("--eth", V1_USAGE)?;
+                let max_tokens: U256 = plus_slippage(exchange.get_token_to_eth_output_price(eth_bought).await?, slippage_bps);
+                exchange.approve_token(&role, max_tokens).await?;
+                exchange.token_to_eth_output(&role, eth_bought, max_tokens, deadline, recipient).await?
+            },
+            "swap" => {
+                // Both hops are quoted, the ETH in between is bounded by the first one.
+                let output: V1Exchange =
+                    match arg_value("--exchange") {
+                        Some(_) => V1Exchange::at(env, artifacts, contract_arg(env, "--exchange", "uniswap_v1exchange")?).await?,
+                        None => V1Exchange::for_token(env, artifacts, required_contract_arg(env, "--output", V1_USAGE)?).await?,
+                    };
+                let by_exchange: bool = arg_value("--exchange").is_some();
+                if arg_value("--tokens").is_some() {
+                    let tokens_sold: U256 = uint_arg("--tokens", V1_USAGE)?;
+                    let eth_bought: U256 = exchange.get_token_to_eth_input_price(tokens_sold).await?;
+                    let min_tokens_bought: U256 = apply_slippage(output.get_eth_to_token_input_price(eth_bought).await?, slippage_bps)?;
+                    let min_eth_bought: U256 = apply_slippage(eth_bought, slippage_bps)?;
+                    exchange.approve_token(&role, tokens_sold).await?;
+                    match by_exchange {
+                        true => exchange.token_to_exchange_input(&role, tokens_sold, min_tokens_bought, min_eth_bought, deadline, recipient, output.address()).await?,
+                        false => exchange.token_to_token_input(&role, tokens_sold, min_tokens_bought, min_eth_bought, deadline, recipient, output.token_address()).await?,
+                    }
+                } else {
+                    let tokens_bought: U256 = uint_arg("--bought", V1_USAGE)?;
+                    let eth_sold: U256 = output.get_eth_to_token_output_price(tokens_bought).await?;
+                    let max_eth_sold: U256 = plus_slippage(eth_sold, slippage_bps);
+                    let max_tokens_sold: U256 = plus_slippage(exchange.get_token_to_eth_output_price(max_eth_sold).await?, slippage_bps);
+                    exchange.approve_token(&role, max_tokens_sold).await?;
+                    match by_exchange {
+                        true => exchange.token_to_exchange_output(&role, tokens_bought, max_tokens_sold, max_eth_sold, deadline, recipient, output.address()).await?,
+                        false => exchange.token_to_token_output(&role, tokens_bought, max_tokens_sold, max_eth_sold, deadline, recipient, output.token_address()).await?,
+                    }
+                }
+            },
+            "remove" => {
+                // The liquidity's share of both reserves, less slippage.
+                let amount: U256 = uint_arg("--amount", V1_USAGE)?;
+                let total_supply: U256 = exchange.total_supply().await?;
+                let (eth_reserve, token_reserve) = exchange.reserves().await?;
+                if total_supply.is_zero() || amount > total_supply {
+                    return Err(DeployError::config(format!("--amount {} is more than the exchange's {} liquidity", amount, total_supply)));
+                }
+                let min_eth: U256 = apply_slippage(amount * eth_reserve / total_supply, slippage_bps)?;
+                let min_tokens: U256 = apply_slippage(amount * token_reserve / total_supply, slippage_bps)?;
+                exchange.remove_liquidity(&role, amount, min_eth, min_tokens, deadline).await?
+            },
+            other => return Err(DeployError::config(format!("unknown v1 action '{}', usage: {}", other, V1_USAGE))),
+        };
+    receipt.report();
+    Ok(())
+}
+
+// The trader needs only gas, the flash swap borrows everything it trades.
+async fn flash_swap(env: &NeonswapEnvironment, artifacts: &ArtifactRegistry) -> DeployResult<()> {
+
+    let borrow: Borrow =
+        match arg_value("--borrow").as_deref() {
+            None | Some("weth") => Borrow::Weth,
+            Some("token") => Borrow::Token,
+            Some(other) => return Err(DeployError::config(format!("--borrow '{}' must be weth or token, usage: {}", other, FLASH_SWAP_USAGE))),
+        };
+    let amount: U256 = if arg_value("--amount").is_some() { uint_arg("--amount", FLASH_SWAP_USAGE)? } else { U256::exp10(17) };
+    let slippage_bps: u64 = optional_u64_arg("--slippage-bps", 50)?;
+    if slippage_bps > 10_000 {
+        return Err(DeployError::config(format!("--slippage-bps {} is more than 100%, usage: {}", slippage_bps, FLASH_SWAP_USAGE)));
+    }
+    let provider: String = arg_value("--provider").unwrap_or_else(|| if env.signers.contains(LIQUIDITY_PROVIDER) { LIQUIDITY_PROVIDER.to_string() } else { DEPLOYER.to_string() });
+
+    let flash_swap: FlashSwap = FlashSwap::new(env, artifacts, contract_arg(env, "--token", "weth_partner")?)?;
+    flash_swap.execute(&role_arg(env, TRADER), &provider, borrow, amount, slippage_bps).await?.report();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn finds_positional_args_between_flags() {
+        assert_eq!(positional_args(&args("deploy governance vote --id 3")), vec!["governance", "vote"]);
+        assert_eq!(positional_args(&args("deploy --profile devnet governance --id 3 vote")), vec!["governance", "vote"]);
+        assert_eq!(positional_args(&args("deploy --profile devnet")), Vec::<String>::new());
+        // a flag value that looks like a command is still a value
+        assert_eq!(positional_args(&args("deploy --token uni migrate")), vec!["migrate"]);
+    }
+
+    #[test]
+    fn rejects_unexpected_positional_args() {
+        assert!(check_positional_args("governance", &args("deploy governance vote --id 3")).is_ok());
+        assert!(check_positional_args("migrate", &args("deploy --profile devnet migrate")).is_ok());
+
+        let error: DeployError = check_positional_args("governance", &args("deploy governance vote 3")).unwrap_err();
+        assert!(error.to_string().contains("unexpected argument '3'"));
+        assert!(check_positional_args("flash-swap", &args("deploy flash-swap weth")).is_err());
+    }
+}
@@ -0,0 +1,383 @@
+use std::collections::{ BTreeMap, HashMap };
+use std::time::Duration;
+use serde::{ Deserialize };
+
+use web3::types::Address;
+
+use crate::error::{ DeployError, DeployResult };
+
+#[derive(Clone)]
+#[derive(Deserialize)]
+pub struct ContractPaths {
+    pub uni: String,
+    pub timelock: String,
+    pub governor_alpha: String,
+    pub weth9: String,
+    pub uniswap_v1factory: String,
+    pub uniswap_v1exchange: String,
+    pub uniswap_v2factory: String,
+    // never deployed directly, pairs are created by the factory
+    pub uniswap_v2pair: String,
+    pub uniswap_v2router01: String,
+    pub uniswap_v2router02: String,
+    pub router_event_emitter: String,
+    pub uniswap_v2migrator: String,
+    pub multicall: String,
+    pub erc20: String,
+    pub erc20wrapper: String,
+    pub example: String,
+}
+
+impl ContractPaths {
+    pub fn entries(&self) -> Vec<(&'static str, &str)> {
+        vec![
+            ("uni",                  &self.uni),
+            ("timelock",             &self.timelock),
+            ("governor_alpha",       &self.governor_alpha),
+            ("weth9",                &self.weth9),
+            ("uniswap_v1factory",    &self.uniswap_v1factory),
+            ("uniswap_v1exchange",   &self.uniswap_v1exchange),
+            ("uniswap_v2factory",    &self.uniswap_v2factory),
+            ("uniswap_v2pair",       &self.uniswap_v2pair),
+            ("uniswap_v2router01",   &self.uniswap_v2router01),
+            ("uniswap_v2router02",   &self.uniswap_v2router02),
+            ("router_event_emitter", &self.router_event_emitter),
+            ("uniswap_v2migrator",   &self.uniswap_v2migrator),
+            ("multicall",            &self.multicall),
+            ("erc20",                &self.erc20),
+            ("erc20wrapper",         &self.erc20wrapper),
+            ("example",              &self.example),
+        ]
+    }
+}
+
+fn default_derivation_path() -> String {
+    "m/44'/60'/0'/0/0".to_string()
+}
+
+#[derive(Clone, Debug)]
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeySource {
+    // plaintext hex key file
+    File(String),
+    // name of an environment variable holding the hex key
+    Env(String),
+    // Web3 Secret Storage (keystore v3) file, the password comes from `password_env`
+    // when it is set and is prompted for otherwise
+    Keystore {
+        path: String,
+        password_env: Option<String>,
+    },
+    // BIP-39 phrase read from `phrase_env` or `phrase_file`
+    Mnemonic {
+        phrase_env: Option<String>,
+        phrase_file: Option<String>,
+        #[serde(default = "default_derivation_path")]
+        derivation_path: String,
+    },
+    // no key in this process, transactions are signed by a JSON-RPC signer at `url`
+    Remote {
+        url: String,
+        address: Address,
+    },
+}
+
+#[derive(Clone, Debug)]
+#[derive(Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum GasLimit {
+    Fixed { gas: u64 },
+    // eth_estimateGas plus a safety margin on top of the estimate
+    Estimate { margin_percent: u64 },
+}
+
+#[derive(Clone, Debug)]
+#[derive(Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum FeeStrategy {
+    Fixed { price: u64 },
+    // eth_gasPrice scaled by the multiplier
+    GasPrice { multiplier: f64 },
+    // EIP-1559 fees from eth_feeHistory: the priority fee is the average reward at
+    // `percentile` over the last `blocks`, the max fee adds the scaled next base fee.
+    FeeHistory { blocks: u64, percentile: f64, base_fee_multiplier: f64 },
+}
+
+#[derive(Clone, Debug)]
+#[derive(Deserialize)]
+pub struct GasOverride {
+    pub limit: Option<GasLimit>,
+    pub fee: Option<FeeStrategy>,
+}
+
+#[derive(Clone, Debug)]
+#[derive(Deserialize)]
+pub struct GasSettings {
+    pub limit: GasLimit,
+    pub fee: FeeStrategy,
+    // operation name (manifest step or environment operation) -> override
+    #[serde(default)]
+    pub operations: HashMap<String,GasOverride>,
+}
+
+impl GasSettings {
+    pub fn limit(&self, operation: &str) -> &GasLimit {
+        self.operations.get(operation)
+            .and_then(|o| o.limit.as_ref())
+            .unwrap_or(&self.limit)
+    }
+    pub fn fee(&self, operation: &str) -> &FeeStrategy {
+        self.operations.get(operation)
+            .and_then(|o| o.fee.as_ref())
+            .unwrap_or(&self.fee)
+    }
+    // A negative or NaN multiplier would otherwise wrap into an absurd fee when scaled.
+    pub fn validate(&self) -> DeployResult<()> {
+        self.fee.validate("gas.fee")?;
+        for (operation, gas) in self.operations.iter() {
+            if let Some(fee) = &gas.fee {
+                fee.validate(&format!("gas.operations.{}.fee", operation))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FeeStrategy {
+    fn validate(&self, field: &str) -> DeployResult<()> {
+        let check_multiplier = |name: &str, value: f64| -> DeployResult<()> {
+            if value.is_finite() && value > 0.0 {
+                Ok(())
+            } else {
+                Err(DeployError::config(format!("{}.{} is {}, it must be a positive number", field, name, value)))
+            }
+        };
+        match self {
+            FeeStrategy::Fixed { .. } => Ok(()),
+            FeeStrategy::GasPrice { multiplier } => check_multiplier("multiplier", *multiplier),
+            FeeStrategy::FeeHistory { blocks, percentile, base_fee_multiplier } => {
+                if *blocks == 0 {
+                    return Err(DeployError::config(format!("{}.blocks must be at least 1", field)));
+                }
+                if !(0.0..=100.0).contains(percentile) {
+                    return Err(DeployError::config(format!("{}.percentile is {}, it must be between 0 and 100", field, percentile)));
+                }
+                check_multiplier("base_fee_multiplier", *base_fee_multiplier)
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct SubmissionSettings {
+    // how long a transaction may stay unmined before it is replaced with higher fees
+    pub timeout_secs: u64,
+    pub max_replacements: u32,
+    pub fee_bump_percent: u64,
+    // operation name -> timeout in seconds
+    pub operation_timeouts: HashMap<String,u64>,
+}
+
+impl Default for SubmissionSettings {
+    fn default() -> Self {
+        SubmissionSettings {
+            timeout_secs: 120,
+            max_replacements: 3,
+            // geth based nodes refuse replacements that bump the fee by less than 10%
+            fee_bump_percent: 15,
+            operation_timeouts: HashMap::new(),
+        }
+    }
+}
+
+impl SubmissionSettings {
+    pub fn timeout(&self, operation: &str) -> Duration {
+        Duration::from_secs(*self.operation_timeouts.get(operation).unwrap_or(&self.timeout_secs))
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct RoleFunding {
+    // whole NEON the deployer tops the role account up to
+    pub neon: u64,
+    // address book name of a test token -> whole tokens to top up to
+    pub tokens: BTreeMap<String,u64>,
+}
+
+#[derive(Clone, Debug)]
+#[derive(Deserialize)]
+pub struct RoleConfig {
+    pub key: KeySource,
+    #[serde(default)]
+    pub funding: RoleFunding,
+}
+
+// Bounds of Timelock.delay, enforced by its constructor and setDelay.
+pub const TIMELOCK_MINIMUM_DELAY_SECS: u64 = 2 * 24 * 3600;
+pub const TIMELOCK_MAXIMUM_DELAY_SECS: u64 = 30 * 24 * 3600;
+// Unix timestamps in seconds stay below this until the year 5138, millisecond ones are far above it.
+pub const MAX_SECONDS_TIMESTAMP: u64 = 100_000_000_000;
+
+// Constructor parameters of the governance contracts, filled into the manifest's
+// `param` arguments. All times are in seconds, as the contracts compare them to block.timestamp.
+#[derive(Clone, Debug)]
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct GovernanceParams {
+    // Uni.mintingAllowedAfter as a unix timestamp, or else this many seconds after deployment
+    pub minting_allowed_after: Option<u64>,
+    pub minting_delay_secs: u64,
+    pub timelock_delay_secs: u64,
+    // receives the initial UNI supply, the deployer when not set
+    pub initial_recipient: Option<Address>,
+    // hands UniswapV2Factory.feeToSetter to the Timelock after deployment, which
+    // leaves governance proposals as the only way to change the fee recipient
+    pub hand_over_fee_setter: bool,
+}
+
+impl Default for GovernanceParams {
+    fn default() -> Self {
+        GovernanceParams {
+            minting_allowed_after: None,
+            minting_delay_secs: 3600,
+            timelock_delay_secs: 3 * 24 * 3600,
+            initial_recipient: None,
+            hand_over_fee_setter: false,
+        }
+    }
+}
+
+impl GovernanceParams {
+    pub fn validate(&self) -> DeployResult<()> {
+        if self.timelock_delay_secs < TIMELOCK_MINIMUM_DELAY_SECS || self.timelock_delay_secs > TIMELOCK_MAXIMUM_DELAY_SECS {
+            return Err(DeployError::config(format!(
+                "timelock_delay_secs {} is outside the Timelock's bounds of {} to {} seconds",
+                self.timelock_delay_secs, TIMELOCK_MINIMUM_DELAY_SECS, TIMELOCK_MAXIMUM_DELAY_SECS,
+            )));
+        }
+        if let Some(timestamp) = self.minting_allowed_after {
+            if timestamp >= MAX_SECONDS_TIMESTAMP {
+                return Err(DeployError::config(format!("minting_allowed_after {} looks like milliseconds, it must be a unix timestamp in seconds", timestamp)));
+            }
+        }
+        if self.minting_delay_secs >= MAX_SECONDS_TIMESTAMP {
+            return Err(DeployError::config(format!("minting_delay_secs {} looks like milliseconds, it must be in seconds", self.minting_delay_secs)));
+        }
+        Ok(())
+    }
+    // Only matters while Uni is still to be deployed, its constructor is the one that rejects it.
+    pub fn check_minting_allowed_after(&self, now: u64) -> DeployResult<()> {
+        match self.minting_allowed_after {
+            Some(timestamp) if timestamp < now =>
+                Err(DeployError::config(format!("minting_allowed_after {} is in the past, Uni requires it to be after deployment", timestamp))),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+#[derive(Deserialize)]
+pub struct NetworkProfile {
+    pub url: String,
+    pub chain_id: u64,
+    pub key: KeySource,
+    // signer roles besides the deployer, e.g. liquidity_provider, trader, voter
+    #[serde(default)]
+    pub roles: BTreeMap<String,RoleConfig>,
+    pub gas: GasSettings,
+    #[serde(default)]
+    pub submission: SubmissionSettings,
+    #[serde(default)]
+    pub governance: GovernanceParams,
+    pub address_book_path: String,
+    pub journal_path: String,
+    pub timelock_queue_path: String,
+}
+
+#[derive(Deserialize)]
+pub struct DeployConfig {
+    pub abi_paths: ContractPaths,
+    #[serde(default)]
+    pub library_paths: HashMap<String,String>,
+    pub manifest_path: String,
+    pub default_profile: String,
+    pub profiles: HashMap<String,NetworkProfile>,
+}
+
+impl DeployConfig {
+    pub fn profile(&self, name: Option<&str>) -> DeployResult<(&str,&NetworkProfile)> {
+        let name: &str = name.unwrap_or(&self.default_profile);
+        match self.profiles.get_key_value(name) {
+            Some((name, profile)) => Ok((name, profile)),
+            None => {
+                let mut known: Vec<&str> = self.profiles.keys().map(|k| k.as_str()).collect();
+                known.sort();
+                Err(DeployError::config(format!("unknown network profile '{}', known profiles: {}", name, known.join(", "))))
+            },
+        }
+    }
+}
+
+pub fn read_deploy_config(path: &str) -> DeployResult<DeployConfig> {
+
+    let file = std::fs::File::open(path).map_err(|e| DeployError::config(format!("config file {} not found: {}", path, e)))?;
+
+    let reader = std::io::BufReader::new(file);
+    serde_json::from_reader(reader).map_err(|e| DeployError::config(format!("config file {} is malformed: {}", path, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gas(json: serde_json::Value) -> GasSettings {
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn accepts_positive_fee_multipliers() {
+        let settings: GasSettings = gas(serde_json::json!({
+            "limit": { "mode": "fixed", "gas": 3000000 },
+            "fee": { "mode": "gas_price", "multiplier": 1.25 },
+            "operations": {
+                "swap": { "fee": { "mode": "fee_history", "blocks": 10, "percentile": 50.0, "base_fee_multiplier": 2.0 } }
+            }
+        }));
+        assert!(settings.validate().is_ok());
+    }
+
+    #[test]
+    fn rejects_negative_and_nan_multipliers() {
+        let negative: GasSettings = gas(serde_json::json!({
+            "limit": { "mode": "fixed", "gas": 3000000 },
+            "fee": { "mode": "gas_price", "multiplier": -1.0 }
+        }));
+        let error: DeployError = negative.validate().unwrap_err();
+        assert!(matches!(error.kind, crate::error::ErrorKind::Config(_)));
+        assert!(error.to_string().contains("gas.fee.multiplier"));
+
+        let mut nan: GasSettings = gas(serde_json::json!({
+            "limit": { "mode": "fixed", "gas": 3000000 },
+            "fee": { "mode": "fixed", "price": 1 }
+        }));
+        nan.operations.insert("swap".to_string(), GasOverride {
+            limit: None,
+            fee: Some(FeeStrategy::FeeHistory { blocks: 10, percentile: 50.0, base_fee_multiplier: f64::NAN }),
+        });
+        assert!(nan.validate().unwrap_err().to_string().contains("gas.operations.swap.fee.base_fee_multiplier"));
+    }
+
+    #[test]
+    fn rejects_an_out_of_range_percentile() {
+        let settings: GasSettings = gas(serde_json::json!({
+            "limit": { "mode": "fixed", "gas": 3000000 },
+            "fee": { "mode": "fee_history", "blocks": 10, "percentile": 150.0, "base_fee_multiplier": 2.0 }
+        }));
+        assert!(settings.validate().unwrap_err().to_string().contains("percentile"));
+    }
+}
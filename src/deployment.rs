@@ -0,0 +1,665 @@
+use std::collections::{ HashMap, HashSet };
+
+use web3::types::{ Address, Bytes, CallRequest, TransactionReceipt, H256, U256 };
+use web3::ethabi::{ Function, StateMutability, Token };
+use web3::contract::{ Contract, Options };
+use web3::signing::keccak256;
+
+use web3_tools::AsEip55;
+
+use crate::artifact::{ Artifact, ArtifactRegistry };
+use crate::address_book::{ AddressBook, ContractRecord, CallRecord };
+use crate::manifest::{ Manifest, ManifestStep, StepAction, CallCheck };
+use crate::verify::{ CodeState, compare_runtime_code };
+use crate::config::{ GasSettings, SubmissionSettings };
+use crate::journal::Journal;
+use crate::nonce::{ NonceManager, Pipeline };
+use crate::gas::{ Fees, GasPlan, GasStrategy, call_request };
+use crate::signing::Signer;
+use crate::error::{ DeployError, DeployResult, ErrorKind, replay_revert_reason };
+
+struct InFlight<'a,'s> {
+    step: &'s ManifestStep,
+    artifact: &'a Artifact,
+    gas_plan: GasPlan,
+    to: Option<Address>,
+    data: Vec<u8>,
+}
+
+struct Resolution {
+    ordered: Vec<ManifestStep>,
+    present: HashSet<String>,
+    addresses: HashMap<String,Address>,
+    completed_calls: HashSet<String>,
+    // found on chain without an address book entry, recorded once the run starts
+    adopted: Vec<String>,
+}
+
+// How many of the deployer's earliest nonces are searched for contracts the address
+// book does not know about.
+const ADOPTION_SCAN_NONCES: u64 = 256;
+
+pub struct DeploymentEngine<'a> {
+    pub web3: &'a web3::Web3<web3::transports::Http>,
+    pub signer: &'a dyn Signer,
+    pub deployer: Address,
+    pub artifacts: &'a ArtifactRegistry,
+    pub gas: &'a GasSettings,
+    pub nonces: &'a NonceManager,
+    pub journal: &'a Journal,
+    pub submission: &'a SubmissionSettings,
+}
+
+// CREATE address: keccak256(rlp([deployer, nonce]))[12..]
+fn predict_contract_address(deployer: Address, nonce: u64) -> Address {
+    let nonce_rlp: Vec<u8> =
+        match nonce {
+            0 => vec![0x80],
+            1..=0x7f => vec![nonce as u8],
+            _ => {
+                let bytes: Vec<u8> = nonce.to_be_bytes().iter().copied().skip_while(|b| *b == 0).collect();
+                let mut encoded: Vec<u8> = vec![0x80 + bytes.len() as u8];
+                encoded.extend(bytes);
+                encoded
+            },
+        };
+    let mut rlp: Vec<u8> = vec![0xc0 + (21 + nonce_rlp.len()) as u8, 0x94];
+    rlp.extend_from_slice(deployer.as_bytes());
+    rlp.extend(nonce_rlp);
+    Address::from_slice(&keccak256(&rlp)[12..])
+}
+
+// Argument-free view functions, whose answers show that code speaks the artifact's ABI.
+fn probe_functions(artifact: &Artifact) -> Vec<&Function> {
+    #[allow(deprecated)]
+    let is_view = |f: &Function| f.constant || matches!(f.state_mutability, StateMutability::View | StateMutability::Pure);
+    artifact.abi.functions()
+        .filter(|f| f.inputs.is_empty() && !f.outputs.is_empty() && is_view(f))
+        .collect()
+}
+
+fn receipt_succeeded(receipt: &TransactionReceipt) -> bool {
+    receipt.status.map(|s| s.as_u64()).unwrap_or(0u64) > 0
+}
+
+impl<'a> DeploymentEngine<'a> {
+    fn artifact(&self, step: &ManifestStep, manifest: &Manifest) -> DeployResult<&'a Artifact> {
+        let artifact: &str =
+            match &step.action {
+                StepAction::Deploy { artifact, .. } => artifact,
+                StepAction::Call { target, .. } => {
+                    match manifest.step(target).map(|s| &s.action) {
+                        Some(StepAction::Deploy { artifact, .. }) => artifact,
+                        _ => return Err(DeployError::manifest(format!("call target '{}' is not a deployment", target)).at_step(&step.name)),
+                    }
+                },
+            };
+        self.artifacts.get(artifact).map_err(|e| e.at_step(&step.name))
+    }
+    fn deploy_data(&self, step: &ManifestStep, artifact: &Artifact, args: &[Token], addresses: &HashMap<String,Address>) -> DeployResult<Vec<u8>> {
+
+        let bytecode: Vec<u8> = artifact.linked_bytecode(addresses).map_err(|e| e.at_step(&step.name))?;
+
+        match artifact.abi.constructor() {
+            Some(constructor) => {
+                constructor.encode_input(bytecode, args)
+                    .map_err(|e| DeployError::manifest(format!("constructor arguments do not fit the ABI: {}", e)).at_step(&step.name).for_contract(&artifact.name))
+            },
+            None => Ok(bytecode),
+        }
+    }
+    fn step_args(&self, step: &ManifestStep, addresses: &HashMap<String,Address>) -> DeployResult<Vec<Token>> {
+        step.args()
+            .iter()
+            .map(|arg| arg.to_token(self.deployer, addresses))
+            .collect::<DeployResult<Vec<Token>>>()
+            .map_err(|e| e.at_step(&step.name))
+    }
+    fn gas_strategy(&self) -> GasStrategy<'_> {
+        GasStrategy::new(self.web3, self.gas)
+    }
+    fn deploy_request(&self, data: &[u8]) -> CallRequest {
+        CallRequest {
+            from: Some(self.deployer),
+            data: Some(Bytes(data.to_vec())),
+            ..CallRequest::default()
+        }
+    }
+    // Builds the transaction of a deployment or call step without sending it.
+    async fn prepare<'s>(&self, step: &'s ManifestStep, artifact: &'a Artifact, addresses: &HashMap<String,Address>) -> DeployResult<InFlight<'a,'s>> {
+
+        let fail = |error: DeployError| error.at_step(&step.name).for_contract(&artifact.name);
+
+        let args: Vec<Token> = self.step_args(step, addresses)?;
+        let (to, data): (Option<Address>, Vec<u8>) =
+            match &step.action {
+                StepAction::Deploy { .. } => (None, self.deploy_data(step, artifact, args.as_slice(), addresses)?),
+                StepAction::Call { target, method, .. } => {
+                    let data: Vec<u8> =
+                        artifact.abi
+                            .function(method)
+                            .and_then(|f| f.encode_input(&args))
+                            .map_err(|e| fail(DeployError::artifact(format!("cannot encode {}: {}", method, e))))?;
+                    (Some(addresses[target]), data)
+                },
+            };
+        let request = CallRequest {
+            from: Some(self.deployer),
+            to,
+            data: Some(Bytes(data.clone())),
+            ..CallRequest::default()
+        };
+        let gas_plan: GasPlan = self.gas_strategy().plan(&step.name, request).await.map_err(fail)?;
+
+        Ok(InFlight { step, artifact, gas_plan, to, data })
+    }
+    async fn conclude(&self, flight: InFlight<'a,'_>, result: DeployResult<TransactionReceipt>, addresses: &HashMap<String,Address>, address_book: &mut AddressBook) -> DeployResult<()> {
+
+        let InFlight { step, artifact, gas_plan, to, data } = flight;
+        let fail = |error: DeployError| error.at_step(&step.name).for_contract(&artifact.name);
+
+        let receipt: TransactionReceipt = result.map_err(fail)?;
+        gas_plan.report(&receipt);
+
+        if !receipt_succeeded(&receipt) {
+            let reason: Option<String> = replay_revert_reason(self.web3, self.deployer, to, Bytes(data), &receipt).await;
+            return Err(fail(DeployError::reverted(reason)).with_tx(receipt.transaction_hash));
+        }
+
+        match &step.action {
+            StepAction::Deploy { .. } => {
+                let address: Address =
+                    receipt.contract_address
+                        .ok_or_else(|| fail(DeployError::rpc("receipt has no contract address")).with_tx(receipt.transaction_hash))?;
+                println!("Deployed {} Address: {}", step.title, address.as_eip55());
+                address_book.record_contract(
+                    ContractRecord {
+                        name: step.name.clone(),
+                        address,
+                        tx_hash: receipt.transaction_hash,
+                        block: receipt.block_number,
+                        deployer: self.deployer,
+                        artifact_hash: artifact.artifact_hash,
+                    }
+                ).map_err(|e| e.at_step(&step.name))?;
+                let presumed_address: Address = addresses[&step.name];
+                if presumed_address != address {
+                    let mismatch = ErrorKind::AddressMismatch { expected: presumed_address, actual: address };
+                    return Err(fail(DeployError::new(mismatch)).with_tx(receipt.transaction_hash));
+                }
+            },
+            StepAction::Call { .. } => {
+                println!("{} Done", step.title);
+                address_book.record_call(
+                    CallRecord {
+                        name: step.name.clone(),
+                        tx_hash: receipt.transaction_hash,
+                        block: receipt.block_number,
+                        sender: self.deployer,
+                    }
+                ).map_err(|e| e.at_step(&step.name))?;
+            },
+        }
+        Ok(())
+    }
+    // Waits for everything in flight and records it. Successful steps are recorded
+    // even when another one in the same batch failed; the first failure is returned.
+    async fn settle(&self, pipeline: &mut Pipeline<'_>, in_flight: &mut HashMap<String,InFlight<'a,'_>>, addresses: &HashMap<String,Address>, address_book: &mut AddressBook) -> DeployResult<()> {
+
+        let mut first_error: Option<DeployError> = None;
+        for (pending, result) in pipeline.wait().await {
+            let flight = in_flight.remove(&pending.label).expect("every submitted step is in flight");
+            if let Err(error) = self.conclude(flight, result, addresses, address_book).await {
+                match first_error {
+                    None => first_error = Some(error),
+                    Some(_) => println!("\n{}\n", error),
+                }
+            }
+        }
+        match first_error {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+    // Artifacts without runtime bytecode (the Vyper V1 contracts) cannot be compared byte
+    // for byte. Their code only counts as theirs if every view function of the ABI answers
+    // with output that decodes.
+    async fn answers_abi(&self, artifact: &Artifact, address: Address) -> DeployResult<bool> {
+        let probes: Vec<&Function> = probe_functions(artifact);
+        if probes.is_empty() {
+            return Ok(false);
+        }
+        for function in probes {
+            let request = CallRequest {
+                from: Some(self.deployer),
+                to: Some(address),
+                data: Some(Bytes(function.encode_input(&[]).map_err(|e| DeployError::artifact(e.to_string()).for_contract(&artifact.name))?)),
+                ..CallRequest::default()
+            };
+            match self.web3.eth().call(request, None).await {
+                Ok(output) if function.decode_output(&output.0).is_ok() => {},
+                Ok(_) | Err(web3::Error::Rpc(_)) => return Ok(false),
+                Err(error) => return Err(DeployError::rpc(error).for_contract(&artifact.name)),
+            }
+        }
+        Ok(true)
+    }
+    // Matches or Differs for code that is there, Missing otherwise; never Unverifiable.
+    async fn identify(&self, artifact: &Artifact, address: Address, code: &[u8]) -> DeployResult<CodeState> {
+        match compare_runtime_code(code, artifact) {
+            CodeState::Unverifiable if self.answers_abi(artifact, address).await? => Ok(CodeState::Matches),
+            CodeState::Unverifiable => Ok(CodeState::Differs),
+            state => Ok(state),
+        }
+    }
+    async fn code_state(&self, artifact: &Artifact, address: Address) -> DeployResult<CodeState> {
+        let code: Bytes = self.web3.eth().code(address, None).await.map_err(|e| DeployError::rpc(e).for_contract(&artifact.name))?;
+        self.identify(artifact, address, &code.0).await
+    }
+    // Contracts missing from the address book may still be on chain, deployed by the old
+    // nonce ladder or recorded in a book that was lost. Whatever the deployer created with
+    // its earlier nonces is matched against the missing steps in nonce order; exact
+    // bytecode matches go first so ABI probing cannot claim them.
+    async fn adopt(&self, missing: Vec<(&ManifestStep, &Artifact)>, transaction_count: u64) -> DeployResult<Vec<(String, Address)>> {
+
+        if missing.is_empty() || transaction_count == 0 {
+            return Ok(Vec::new());
+        }
+        let scanned: u64 = transaction_count.min(ADOPTION_SCAN_NONCES);
+        if scanned < transaction_count {
+            println!("Looking for earlier deployments in the first {} of {} deployer nonces", scanned, transaction_count);
+        }
+
+        let mut candidates: Vec<(u64, Address, Vec<u8>)> = Vec::new();
+        for nonce in 0..scanned {
+            let address: Address = predict_contract_address(self.deployer, nonce);
+            let code: Bytes = self.web3.eth().code(address, None).await.map_err(DeployError::rpc)?;
+            if !code.0.is_empty() {
+                candidates.push((nonce, address, code.0));
+            }
+        }
+
+        let (exact, probed): (Vec<_>, Vec<_>) = missing.into_iter().partition(|(_, artifact)| artifact.runtime_bytecode().is_some());
+        let mut adopted: Vec<(String, Address)> = Vec::new();
+        for (step, artifact) in exact.into_iter().chain(probed) {
+            let mut found: Option<usize> = None;
+            for (i, (_, address, code)) in candidates.iter().enumerate() {
+                if self.identify(artifact, *address, code).await.map_err(|e| e.at_step(&step.name))? == CodeState::Matches {
+                    found = Some(i);
+                    break;
+                }
+            }
+            if let Some(i) = found {
+                let (nonce, address, _) = candidates.remove(i);
+                println!("{} found at {} (deployer nonce {}), adopting it", step.title, address.as_eip55(), nonce);
+                adopted.push((step.name.clone(), address));
+            }
+        }
+        Ok(adopted)
+    }
+    async fn call_confirmed(&self, artifact: &Artifact, target: Address, check: &CallCheck, addresses: &HashMap<String,Address>) -> DeployResult<bool> {
+        let expected: Token = check.expect.to_token(self.deployer, addresses)?;
+        let contract: Contract<web3::transports::Http> = artifact.at(self.web3, target);
+        let actual: Token =
+            contract.query(&check.method, (), self.deployer, Options::default(), None)
+                .await
+                .map_err(|e| DeployError::rpc(e).for_contract(&artifact.name))?;
+        Ok(actual == expected)
+    }
+    async fn resolve(&self, manifest: &Manifest, transaction_count: u64, address_book: &AddressBook) -> DeployResult<Resolution> {
+
+        let ordered: Vec<ManifestStep> = manifest.with_libraries(self.artifacts)?;
+
+        // A recorded contract only counts as deployed if its address holds the artifact's code.
+        let mut present: HashSet<String> = HashSet::new();
+        let mut located: HashMap<String,Address> = HashMap::new();
+        let mut missing: Vec<(&ManifestStep, &Artifact)> = Vec::new();
+        for step in ordered.iter().filter(|s| s.is_deploy()) {
+            let artifact: &Artifact = self.artifact(step, manifest)?;
+            let address: Address =
+                match address_book.address(&step.name) {
+                    Some(address) => address,
+                    None => {
+                        missing.push((step, artifact));
+                        continue;
+                    },
+                };
+            match self.code_state(artifact, address).await.map_err(|e| e.at_step(&step.name))? {
+                CodeState::Matches => {
+                    present.insert(step.name.clone());
+                    located.insert(step.name.clone(), address);
+                },
+                CodeState::Missing => {
+                    println!("{} recorded at {} has no code, it will be redeployed", step.title, address.as_eip55());
+                    missing.push((step, artifact));
+                },
+                CodeState::Differs | CodeState::Unverifiable => {
+                    return Err(DeployError::new(ErrorKind::CodeMismatch(address)).at_step(&step.name).for_contract(&artifact.name));
+                },
+            }
+        }
+        let mut adopted: Vec<String> = Vec::new();
+        for (name, address) in self.adopt(missing, transaction_count).await? {
+            present.insert(name.clone());
+            located.insert(name.clone(), address);
+            adopted.push(name);
+        }
+
+        // Contracts found on chain keep their recorded address; the rest are predicted
+        // from the nonces they will be sent with. Calls are confirmed by querying state.
+        let mut addresses: HashMap<String,Address> = HashMap::new();
+        let mut completed_calls: HashSet<String> = HashSet::new();
+        let mut nonce: u64 = transaction_count;
+        for step in ordered.iter() {
+            match &step.action {
+                StepAction::Deploy { .. } => {
+                    let address: Address =
+                        if let Some(address) = located.get(&step.name) {
+                            *address
+                        } else {
+                            nonce += 1;
+                            predict_contract_address(self.deployer, nonce - 1)
+                        };
+                    addresses.insert(step.name.clone(), address);
+                },
+                StepAction::Call { target, check, .. } => {
+                    let completed: bool =
+                        if !present.contains(target) {
+                            false
+                        } else if let Some(check) = check {
+                            let artifact: &Artifact = self.artifact(step, manifest)?;
+                            self.call_confirmed(artifact, addresses[target], check, &addresses).await.map_err(|e| e.at_step(&step.name))?
+                        } else {
+                            address_book.calls.contains_key(&step.name)
+                        };
+                    if completed {
+                        completed_calls.insert(step.name.clone());
+                    } else {
+                        nonce += 1;
+                    }
+                },
+            }
+        }
+
+        Ok(Resolution { ordered, present, addresses, completed_calls, adopted })
+    }
+    pub async fn plan(&self, manifest: &Manifest, transaction_count: u64, address_book: &AddressBook) -> DeployResult<U256> {
+
+        let Resolution { ordered, present, addresses, completed_calls, .. } = self.resolve(manifest, transaction_count, address_book).await?;
+
+        let gas_strategy: GasStrategy = self.gas_strategy();
+        let mut total_gas: U256 = U256::zero();
+        let mut total_cost: U256 = U256::zero();
+        let mut estimated: bool = true;
+
+        for step in ordered.iter() {
+            let artifact: &Artifact = self.artifact(step, manifest)?;
+            let args: Vec<Token> = self.step_args(step, &addresses)?;
+
+            let gas: Result<U256,String> =
+                match &step.action {
+                    StepAction::Deploy { .. } => {
+                        if present.contains(&step.name) {
+                            println!("{} Exists at Address: {}", step.title, addresses[&step.name].as_eip55());
+                            continue;
+                        }
+                        let data: Vec<u8> = self.deploy_data(step, artifact, args.as_slice(), &addresses)?;
+                        println!("Deploy {} at {}", step.title, addresses[&step.name].as_eip55());
+                        println!("    constructor args: 0x{}", hex::encode(web3::ethabi::encode(&args)));
+                        gas_strategy.estimate(self.deploy_request(&data)).await.map_err(|e| e.kind.to_string())
+                    },
+                    StepAction::Call { target, method, .. } => {
+                        if completed_calls.contains(&step.name) {
+                            println!("{} Done Already", step.title);
+                            continue;
+                        }
+                        println!("Call {}.{} on {}", target, method, addresses[target].as_eip55());
+                        println!("    args: 0x{}", hex::encode(web3::ethabi::encode(&args)));
+                        if present.contains(target) {
+                            let contract: Contract<web3::transports::Http> = artifact.at(self.web3, addresses[target]);
+                            let request: CallRequest = call_request(&contract, method, args.as_slice(), self.deployer).map_err(|e| e.at_step(&step.name))?;
+                            gas_strategy.estimate(request).await.map_err(|e| e.kind.to_string())
+                        } else {
+                            Err(format!("{} is not deployed yet", target))
+                        }
+                    },
+                };
+
+            match gas {
+                Ok(gas) => {
+                    let fees: Fees = gas_strategy.fees(&step.name).await.map_err(|e| e.at_step(&step.name))?;
+                    println!("    estimated gas: {} @ {}", gas, fees);
+                    total_gas += gas;
+                    total_cost += gas * fees.max_price();
+                },
+                Err(error) => {
+                    println!("    estimated gas: unknown ({})", error);
+                    estimated = false;
+                },
+            }
+        }
+
+        println!("\nTotal Gas: {}{}", total_gas, if estimated { "" } else { " (excluding steps that could not be estimated)" });
+        println!("Total Cost: {} (at the maximum fee per gas)", total_cost);
+
+        Ok(total_cost)
+    }
+    // Independent steps are submitted back to back with locally assigned nonces and
+    // confirmed together; a step that depends on one still in flight waits for the batch.
+    pub async fn run(&self, manifest: &Manifest, transaction_count: u64, address_book: &mut AddressBook) -> DeployResult<HashMap<String,Address>> {
+
+        let Resolution { ordered, present, addresses, completed_calls, adopted } = self.resolve(manifest, transaction_count, address_book).await?;
+
+        // The creating transaction of an adopted contract is not known, its hash stays zero.
+        for name in adopted.iter() {
+            let step: &ManifestStep = ordered.iter().find(|s| &s.name == name).expect("adopted steps come from the manifest");
+            address_book.record_contract(
+                ContractRecord {
+                    name: name.clone(),
+                    address: addresses[name],
+                    tx_hash: H256::zero(),
+                    block: None,
+                    deployer: self.deployer,
+                    artifact_hash: self.artifact(step, manifest)?.artifact_hash,
+                }
+            ).map_err(|e| e.at_step(name))?;
+        }
+
+        let mut pipeline: Pipeline = Pipeline::new(self.web3, self.signer, self.nonces, self.journal, self.submission);
+        let mut in_flight: HashMap<String,InFlight> = HashMap::new();
+
+        for step in ordered.iter() {
+            let artifact: &Artifact = self.artifact(step, manifest)?;
+
+            match &step.action {
+                StepAction::Deploy { .. } if present.contains(&step.name) => {
+                    println!("{} Exists at Address: {}", step.title, addresses[&step.name].as_eip55());
+                    continue;
+                },
+                StepAction::Call { .. } if completed_calls.contains(&step.name) => {
+                    println!("{} Done Already", step.title);
+                    continue;
+                },
+                _ => {},
+            }
+
+            let waits_on_flight: bool =
+                step.dependencies()
+                    .into_iter()
+                    .chain(artifact.library_names())
+                    .any(|name| in_flight.contains_key(name));
+            if waits_on_flight {
+                self.settle(&mut pipeline, &mut in_flight, &addresses, address_book).await?;
+            }
+
+            let submitted: DeployResult<InFlight> =
+                match self.prepare(step, artifact, &addresses).await {
+                    Ok(flight) => {
+                        let tx = flight.gas_plan.transaction(flight.to, Bytes(flight.data.clone()));
+                        pipeline.submit(&step.name, tx).await.map(|hash| {
+                            println!("Submitted {} ({:?})", step.title, hash);
+                            flight
+                        })
+                    },
+                    Err(error) => Err(error),
+                };
+            match submitted {
+                Ok(flight) => {
+                    in_flight.insert(step.name.clone(), flight);
+                },
+                Err(error) => {
+                    // Record whatever is already on its way before giving up.
+                    if let Err(settle_error) = self.settle(&mut pipeline, &mut in_flight, &addresses, address_book).await {
+                        println!("\n{}\n", settle_error);
+                    }
+                    return Err(error.for_contract(&artifact.name));
+                },
+            }
+        }
+        if !pipeline.is_empty() {
+            self.settle(&mut pipeline, &mut in_flight, &addresses, address_book).await?;
+        }
+
+        Ok(addresses)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+    use serde_json::{ json, Value };
+    use super::*;
+    use crate::address_book::tests::address_book;
+    use crate::artifact::tests::registry;
+    use crate::signing::LocalSigner;
+    use crate::test_node::TestNode;
+
+    const KEY: &str = "4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318";
+
+    fn truffle(runtime: &str) -> Value {
+        json!({ "abi": [], "bytecode": format!("0x{}", runtime), "deployedBytecode": format!("0x{}", runtime) })
+    }
+
+    // A Vyper artifact without runtime bytecode, like the V1 contracts.
+    fn vyper() -> Value {
+        json!({
+            "abi": [ { "type": "function", "name": "tokenCount", "inputs": [], "outputs": [ { "name": "", "type": "uint256" } ], "constant": true } ],
+            "evm": { "bytecode": { "object": "0x6080" }, "deployedBytecode": { "object": "" } },
+        })
+    }
+
+    fn deploy_steps(steps: &[(&str, &str)]) -> Manifest {
+        let steps: Vec<Value> = steps.iter().map(|(name, artifact)| json!({ "name": name, "title": name, "deploy": { "artifact": artifact } })).collect();
+        serde_json::from_value(json!({ "steps": steps })).unwrap()
+    }
+
+    // Serves `code` at the given addresses; `answering` addresses reply to eth_call with a uint.
+    fn chain(code: Vec<(Address, &'static str)>, answering: Vec<Address>) -> TestNode {
+        TestNode::start(move |method, params| {
+            let address: Address = serde_json::from_value(if method == "eth_call" { params[0]["to"].clone() } else { params[0].clone() }).unwrap();
+            match method {
+                "eth_getCode" => Ok(json!(code.iter().find(|(a, _)| *a == address).map(|(_, code)| format!("0x{}", code)).unwrap_or_else(|| "0x".to_string()))),
+                "eth_call" if answering.contains(&address) => Ok(json!(Bytes(web3::ethabi::encode(&[Token::Uint(U256::from(3))])))),
+                "eth_call" => Err(json!({ "code": -32000, "message": "execution reverted" })),
+                _ => Err(json!({ "code": -32601, "message": "method not found" })),
+            }
+        })
+    }
+
+    async fn resolve_on(node: &TestNode, artifacts: &ArtifactRegistry, manifest: &Manifest, transaction_count: u64, book: &AddressBook) -> DeployResult<Resolution> {
+        let web3 = node.web3();
+        let signer: LocalSigner = LocalSigner::from_hex(KEY).unwrap();
+        let gas: GasSettings = serde_json::from_value(json!({ "limit": { "mode": "fixed", "gas": 3000000 }, "fee": { "mode": "fixed", "price": 1 } })).unwrap();
+        let nonces: NonceManager = NonceManager::new(signer.address());
+        let journal: Journal = Journal::new("unused-journal.jsonl");
+        let submission: SubmissionSettings = SubmissionSettings::default();
+        let engine = DeploymentEngine {
+            web3: &web3,
+            signer: &signer,
+            deployer: signer.address(),
+            artifacts,
+            gas: &gas,
+            nonces: &nonces,
+            journal: &journal,
+            submission: &submission,
+        };
+        engine.resolve(manifest, transaction_count, book).await
+    }
+
+    #[tokio::test]
+    async fn adopts_earlier_deployments_missing_from_the_address_book() {
+        let deployer: Address = LocalSigner::from_hex(KEY).unwrap().address();
+        let at = |nonce: u64| predict_contract_address(deployer, nonce);
+        // nonce 1 was a plain transfer, nonce 4 a deployment of something else
+        let node: TestNode = chain(vec![(at(0), "6001"), (at(2), "6001"), (at(3), "6002"), (at(4), "6009")], vec![]);
+        let artifacts: ArtifactRegistry = registry(vec![("erc20", truffle("6001")), ("multicall", truffle("6002")), ("example", truffle("6003"))]);
+        let manifest: Manifest = deploy_steps(&[("token_a", "erc20"), ("multicall", "multicall"), ("token_b", "erc20"), ("example", "example")]);
+
+        let resolution: Resolution = resolve_on(&node, &artifacts, &manifest, 5, &AddressBook::default()).await.unwrap();
+        assert_eq!(resolution.adopted, vec!["token_a", "multicall", "token_b"]);
+        assert_eq!(resolution.addresses["token_a"], at(0));
+        assert_eq!(resolution.addresses["token_b"], at(2));
+        assert_eq!(resolution.addresses["multicall"], at(3));
+        // only the step that is really missing gets a new address
+        assert!(!resolution.present.contains("example"));
+        assert_eq!(resolution.addresses["example"], at(5));
+    }
+
+    #[tokio::test]
+    async fn keeps_recorded_contracts_and_adopts_the_rest() {
+        let deployer: Address = LocalSigner::from_hex(KEY).unwrap().address();
+        let at = |nonce: u64| predict_contract_address(deployer, nonce);
+        let node: TestNode = chain(vec![(at(0), "6001"), (Address::repeat_byte(0x77), "6002")], vec![]);
+        let artifacts: ArtifactRegistry = registry(vec![("erc20", truffle("6001")), ("multicall", truffle("6002"))]);
+        let manifest: Manifest = deploy_steps(&[("token_a", "erc20"), ("multicall", "multicall")]);
+
+        let book: AddressBook = address_book(&[("multicall", Address::repeat_byte(0x77))]);
+        let resolution: Resolution = resolve_on(&node, &artifacts, &manifest, 2, &book).await.unwrap();
+        assert_eq!(resolution.adopted, vec!["token_a"]);
+        assert_eq!(resolution.addresses["multicall"], Address::repeat_byte(0x77));
+        assert_eq!(resolution.addresses["token_a"], at(0));
+    }
+
+    #[tokio::test]
+    async fn probes_contracts_without_runtime_bytecode() {
+        let deployer: Address = LocalSigner::from_hex(KEY).unwrap().address();
+        let at = |nonce: u64| predict_contract_address(deployer, nonce);
+        let artifacts: ArtifactRegistry = registry(vec![("uniswap_v1factory", vyper())]);
+        let manifest: Manifest = deploy_steps(&[("uniswap_v1factory", "uniswap_v1factory")]);
+
+        // code that answers the ABI is adopted
+        let node: TestNode = chain(vec![(at(0), "60ff")], vec![at(0)]);
+        let resolution: Resolution = resolve_on(&node, &artifacts, &manifest, 1, &AddressBook::default()).await.unwrap();
+        assert_eq!(resolution.adopted, vec!["uniswap_v1factory"]);
+
+        // any other code is not
+        let node: TestNode = chain(vec![(at(0), "60ff")], vec![]);
+        let resolution: Resolution = resolve_on(&node, &artifacts, &manifest, 1, &AddressBook::default()).await.unwrap();
+        assert!(resolution.adopted.is_empty());
+        assert_eq!(resolution.addresses["uniswap_v1factory"], at(1));
+
+        // and a recorded address holding it is a mismatch, not a deployed contract
+        let book: AddressBook = address_book(&[("uniswap_v1factory", at(0))]);
+        let error: DeployError = resolve_on(&node, &artifacts, &manifest, 1, &book).await.err().unwrap();
+        assert!(matches!(error.kind, ErrorKind::CodeMismatch(address) if address == at(0)), "{}", error);
+    }
+
+    // Expected addresses from an independent RLP encoder; nonces 0 and 1 are the
+    // widely published vectors for this deployer.
+    #[test]
+    fn predicts_create_addresses_across_nonce_encodings() {
+        let deployer: Address = Address::from_str("6ac7ea33f8831ea9dcc53393aaa88b25a785dbf0").unwrap();
+        let cases: [(u64, &str); 7] = [
+            (0, "cd234a471b72ba2f1ccf0a70fcaba648a5eecd8d"),
+            (1, "343c43a37d37dff08ae8c4a11544c718abb4fcf8"),
+            (0x7f, "06d9a77f5e4b311bae8d559db9cdb4df94104aa0"),
+            (0x80, "08e190dcb7b73f5fcdabb43e102215c83659a76d"),
+            (0xff, "3ef7c1a519e4b4431e317d7839340e3139b03c65"),
+            (0x100, "3837c1ae70354f670550c746580199ac6a73cb0a"),
+            (0x10000, "f666a819b370d38f44f2573464da3fba8479b917"),
+        ];
+        for (nonce, expected) in cases {
+            assert_eq!(predict_contract_address(deployer, nonce), Address::from_str(expected).unwrap(), "nonce {:#x}", nonce);
+        }
+    }
+}
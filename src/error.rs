@@ -0,0 +1,225 @@
+use std::fmt;
+
+use web3::types::{ Address, Bytes, CallRequest, BlockId, BlockNumber, TransactionReceipt, H256, U256 };
+use web3::ethabi::{ ParamType, Token };
+
+use web3_tools::AsEip55;
+
+#[derive(Debug)]
+pub enum ErrorKind {
+    Config(String),
+    Manifest(String),
+    Artifact(String),
+    AddressBook(String),
+    Rpc(String),
+    Signing(String),
+    Reverted(Option<String>),
+    AddressMismatch { expected: Address, actual: Address },
+    CodeMismatch(Address),
+    Verification(String),
+    InsufficientBalance { balance: String, required: String },
+    Stuck { nonce: U256, attempts: u32 },
+}
+
+#[derive(Debug)]
+pub struct DeployError {
+    pub kind: ErrorKind,
+    pub step: Option<String>,
+    pub contract: Option<String>,
+    pub tx_hash: Option<H256>,
+}
+
+pub type DeployResult<T> = Result<T,DeployError>;
+
+impl DeployError {
+    pub fn new(kind: ErrorKind) -> DeployError {
+        DeployError {
+            kind,
+            step: None,
+            contract: None,
+            tx_hash: None,
+        }
+    }
+    pub fn config(message: impl Into<String>) -> DeployError {
+        DeployError::new(ErrorKind::Config(message.into()))
+    }
+    pub fn manifest(message: impl Into<String>) -> DeployError {
+        DeployError::new(ErrorKind::Manifest(message.into()))
+    }
+    pub fn artifact(message: impl Into<String>) -> DeployError {
+        DeployError::new(ErrorKind::Artifact(message.into()))
+    }
+    pub fn address_book(message: impl Into<String>) -> DeployError {
+        DeployError::new(ErrorKind::AddressBook(message.into()))
+    }
+    pub fn rpc(error: impl fmt::Debug) -> DeployError {
+        DeployError::new(ErrorKind::Rpc(format!("{:?}", error)))
+    }
+    pub fn signing(error: impl fmt::Debug) -> DeployError {
+        DeployError::new(ErrorKind::Signing(format!("{:?}", error)))
+    }
+    pub fn reverted(reason: Option<String>) -> DeployError {
+        DeployError::new(ErrorKind::Reverted(reason))
+    }
+    pub fn at_step(mut self, step: &str) -> DeployError {
+        self.step.get_or_insert_with(|| step.to_string());
+        self
+    }
+    pub fn for_contract(mut self, contract: &str) -> DeployError {
+        self.contract.get_or_insert_with(|| contract.to_string());
+        self
+    }
+    pub fn with_tx(mut self, tx_hash: H256) -> DeployError {
+        self.tx_hash = Some(tx_hash);
+        self
+    }
+    pub fn exit_code(&self) -> i32 {
+        match self.kind {
+            ErrorKind::Config(_)                  => 2,
+            ErrorKind::Manifest(_)                => 3,
+            ErrorKind::Artifact(_)                => 4,
+            ErrorKind::AddressBook(_)             => 5,
+            ErrorKind::Rpc(_)                     => 6,
+            ErrorKind::Signing(_)                 => 7,
+            ErrorKind::Reverted(_)                => 8,
+            ErrorKind::AddressMismatch { .. }     => 9,
+            ErrorKind::CodeMismatch(_)            => 10,
+            ErrorKind::Verification(_)            => 11,
+            ErrorKind::InsufficientBalance { .. } => 12,
+            ErrorKind::Stuck { .. }               => 13,
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorKind::Config(message)      => write!(f, "Configuration error: {}", message),
+            ErrorKind::Manifest(message)    => write!(f, "Manifest error: {}", message),
+            ErrorKind::Artifact(message)    => write!(f, "Artifact error: {}", message),
+            ErrorKind::AddressBook(message) => write!(f, "Address book error: {}", message),
+            ErrorKind::Rpc(message)         => write!(f, "RPC error: {}", message),
+            ErrorKind::Signing(message)     => write!(f, "Signing error: {}", message),
+            ErrorKind::Reverted(Some(reason)) => write!(f, "Transaction reverted: {}", reason),
+            ErrorKind::Reverted(None)         => write!(f, "Transaction reverted without a reason"),
+            ErrorKind::AddressMismatch { expected, actual } =>
+                write!(f, "Contract deployed at {} instead of the expected {}", actual.as_eip55(), expected.as_eip55()),
+            ErrorKind::CodeMismatch(address) => write!(f, "Code at {} does not match its artifact", address.as_eip55()),
+            ErrorKind::Verification(message) => write!(f, "Verification failed: {}", message),
+            ErrorKind::InsufficientBalance { balance, required } =>
+                write!(f, "Balance {} does not cover the required {}", balance, required),
+            ErrorKind::Stuck { nonce, attempts } =>
+                write!(f, "Transaction with nonce {} was not mined after {} attempts", nonce, attempts),
+        }
+    }
+}
+
+impl fmt::Display for DeployError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        if let Some(step) = &self.step {
+            write!(f, "\n    step:     {}", step)?;
+        }
+        if let Some(contract) = &self.contract {
+            write!(f, "\n    contract: {}", contract)?;
+        }
+        if let Some(tx_hash) = &self.tx_hash {
+            write!(f, "\n    tx hash:  {:?}", tx_hash)?;
+        }
+        Ok(())
+    }
+}
+
+// Decodes `Error(string)` and `Panic(uint256)` revert payloads.
+pub fn decode_revert_data(data: &[u8]) -> Option<String> {
+    if data.len() < 4 {
+        return None;
+    }
+    match data[..4] {
+        [0x08, 0xc3, 0x79, 0xa0] => {
+            match web3::ethabi::decode(&[ParamType::String], &data[4..]).ok()?.pop()? {
+                Token::String(reason) => Some(reason),
+                _ => None,
+            }
+        },
+        [0x4e, 0x48, 0x7b, 0x71] => {
+            match web3::ethabi::decode(&[ParamType::Uint(256)], &data[4..]).ok()?.pop()? {
+                Token::Uint(code) => Some(format!("panic code {:#x}", code)),
+                _ => None,
+            }
+        },
+        _ => None,
+    }
+}
+
+pub fn revert_reason(error: &web3::Error) -> Option<String> {
+    match error {
+        web3::Error::Rpc(rpc_error) => {
+            rpc_error.data
+                .as_ref()
+                .and_then(|data| data.as_str())
+                .and_then(|data| hex::decode(data.trim_start_matches("0x")).ok())
+                .and_then(|data| decode_revert_data(&data))
+                .or_else(|| Some(rpc_error.message.clone()))
+        },
+        _ => None,
+    }
+}
+
+// Replays a reverted transaction as an eth_call against the block before it was
+// mined, which is the cheapest way to get the revert reason out of the node.
+pub async fn replay_revert_reason(web3: &web3::Web3<web3::transports::Http>, from: Address, to: Option<Address>, data: Bytes, receipt: &TransactionReceipt) -> Option<String> {
+    let call = CallRequest {
+        from: Some(from),
+        to,
+        data: Some(data),
+        ..CallRequest::default()
+    };
+    let block: Option<BlockId> =
+        receipt.block_number
+            .filter(|n| n.as_u64() > 0)
+            .map(|n| BlockId::Number(BlockNumber::Number(n - 1)));
+    match web3.eth().call(call, block).await {
+        Ok(_) => None,
+        Err(error) => revert_reason(&error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn revert_data(selector: [u8; 4], params: &[Token]) -> Vec<u8> {
+        let mut data: Vec<u8> = selector.to_vec();
+        data.extend(web3::ethabi::encode(params));
+        data
+    }
+
+    #[test]
+    fn decodes_an_error_string() {
+        let data: Vec<u8> = revert_data([0x08, 0xc3, 0x79, 0xa0], &[Token::String("UniswapV2: EXPIRED".to_string())]);
+        assert_eq!(decode_revert_data(&data).as_deref(), Some("UniswapV2: EXPIRED"));
+    }
+
+    #[test]
+    fn decodes_a_panic_code() {
+        let data: Vec<u8> = revert_data([0x4e, 0x48, 0x7b, 0x71], &[Token::Uint(U256::from(0x11))]);
+        assert_eq!(decode_revert_data(&data).as_deref(), Some("panic code 0x11"));
+    }
+
+    #[test]
+    fn ignores_empty_and_unknown_data() {
+        assert_eq!(decode_revert_data(&[]), None);
+        assert_eq!(decode_revert_data(&[0x08, 0xc3, 0x79]), None);
+        assert_eq!(decode_revert_data(&revert_data([0xde, 0xad, 0xbe, 0xef], &[Token::Uint(U256::one())])), None);
+    }
+
+    #[test]
+    fn ignores_truncated_payloads() {
+        let error: Vec<u8> = revert_data([0x08, 0xc3, 0x79, 0xa0], &[Token::String("UniswapV2: EXPIRED".to_string())]);
+        assert_eq!(decode_revert_data(&error[..error.len() - 32]), None);
+        assert_eq!(decode_revert_data(&error[..4]), None);
+        let panic: Vec<u8> = revert_data([0x4e, 0x48, 0x7b, 0x71], &[Token::Uint(U256::from(0x11))]);
+        assert_eq!(decode_revert_data(&panic[..20]), None);
+    }
+}
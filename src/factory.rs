@@ -0,0 +1,183 @@
+use web3::types::{ Address, U256 };
+use web3::contract::Contract;
+
+use web3_tools::AsEip55;
+
+use crate::artifact::{ Artifact, ArtifactRegistry };
+use crate::liquidity::NeonswapEnvironment;
+use crate::error::{ DeployError, DeployResult };
+
+// The protocol fee of one pair as UniswapV2Pair._mintFee would mint it on the next
+// mint or burn: a sixth of the growth in sqrt(k) since kLast, as new liquidity for feeTo.
+pub struct PairFee {
+    pub pair: Address,
+    pub token0: Address,
+    pub token1: Address,
+    pub reserve0: U256,
+    pub reserve1: U256,
+    pub k_last: U256,
+    pub total_supply: U256,
+    pub liquidity: U256,
+}
+
+impl PairFee {
+    pub fn mint_fee(&self, fee_on: bool) -> U256 {
+        if !fee_on || self.k_last.is_zero() {
+            return U256::zero();
+        }
+        let root_k: U256 = (self.reserve0 * self.reserve1).integer_sqrt();
+        let root_k_last: U256 = self.k_last.integer_sqrt();
+        if root_k <= root_k_last {
+            return U256::zero();
+        }
+        let numerator: U256 = self.total_supply * (root_k - root_k_last);
+        let denominator: U256 = root_k * 5 + root_k_last;
+        numerator / denominator
+    }
+    // The reserves the owed liquidity would be worth once minted.
+    pub fn owed_amounts(&self) -> (U256, U256) {
+        let supply: U256 = self.total_supply + self.liquidity;
+        if supply.is_zero() {
+            return (U256::zero(), U256::zero());
+        }
+        (self.reserve0 * self.liquidity / supply, self.reserve1 * self.liquidity / supply)
+    }
+    pub fn report(&self) {
+        let (amount0, amount1) = self.owed_amounts();
+        println!("Pair {}", self.pair.as_eip55());
+        println!("    token0 {} reserve {}", self.token0.as_eip55(), self.reserve0);
+        println!("    token1 {} reserve {}", self.token1.as_eip55(), self.reserve1);
+        println!("    kLast {}, k now {}", self.k_last, self.reserve0 * self.reserve1);
+        println!("    fee owed {} liquidity of {} total, worth {} token0 and {} token1", self.liquidity, self.total_supply, amount0, amount1);
+    }
+}
+
+// Typed access to UniswapV2Factory's fee switch and the fees its pairs have accrued.
+pub struct FactoryClient<'a> {
+    env: &'a NeonswapEnvironment,
+    pair_artifact: &'a Artifact,
+    contract: Contract<web3::transports::Http>,
+}
+
+impl<'a> FactoryClient<'a> {
+    pub fn new(env: &'a NeonswapEnvironment, artifacts: &'a ArtifactRegistry) -> DeployResult<FactoryClient<'a>> {
+        let contract: Contract<web3::transports::Http> = artifacts.contract(&env.web3, "uniswap_v2factory", env.contract_address("uniswap_v2factory")?)?;
+        Ok(FactoryClient { env, pair_artifact: artifacts.get("uniswap_v2pair")?, contract })
+    }
+    pub async fn fee_to(&self) -> DeployResult<Address> {
+        self.env.query(&self.contract, "uniswap_v2factory", "feeTo", ()).await
+    }
+    pub async fn fee_to_setter(&self) -> DeployResult<Address> {
+        self.env.query(&self.contract, "uniswap_v2factory", "feeToSetter", ()).await
+    }
+    pub async fn pairs(&self) -> DeployResult<Vec<Address>> {
+        let count: U256 = self.env.query(&self.contract, "uniswap_v2factory", "allPairsLength", ()).await?;
+        let mut pairs: Vec<Address> = Vec::new();
+        for index in 0..count.as_u64() {
+            pairs.push(self.env.query(&self.contract, "uniswap_v2factory", "allPairs", U256::from(index)).await?);
+        }
+        Ok(pairs)
+    }
+    // The factory only accepts these from feeToSetter, so the role has to hold it.
+    // Once the Timelock holds it, a governance proposal has to make the change.
+    async fn check_setter(&self, role: &str) -> DeployResult<()> {
+        let setter: Address = self.fee_to_setter().await?;
+        if setter == self.env.signers.get(role)?.address {
+            return Ok(());
+        }
+        let hint: &str = if self.env.address_book.address("timelock") == Some(setter) { ", propose the change through governance instead" } else { "" };
+        Err(DeployError::config(format!("{} is not the feeToSetter, {} is{}", role, setter.as_eip55(), hint)).for_contract("uniswap_v2factory"))
+    }
+    pub async fn set_fee_to(&self, role: &str, fee_to: Address) -> DeployResult<()> {
+        self.check_setter(role).await?;
+        self.env.send_call(role, "factory_set_fee_to", &self.contract, "setFeeTo", fee_to)
+            .await
+            .map(|_| ())
+            .map_err(|e| e.for_contract("uniswap_v2factory"))
+    }
+    pub async fn set_fee_to_setter(&self, role: &str, setter: Address) -> DeployResult<()> {
+        self.check_setter(role).await?;
+        self.env.send_call(role, "factory_set_fee_to_setter", &self.contract, "setFeeToSetter", setter)
+            .await
+            .map(|_| ())
+            .map_err(|e| e.for_contract("uniswap_v2factory"))
+    }
+    pub async fn pair_fee(&self, pair: Address, fee_on: bool) -> DeployResult<PairFee> {
+        let contract: Contract<web3::transports::Http> = self.pair_artifact.at(&self.env.web3, pair);
+        let (reserve0, reserve1, _): (U256, U256, u32) = self.env.query(&contract, "uniswap_v2pair", "getReserves", ()).await?;
+        let mut fee = PairFee {
+            pair,
+            token0: self.env.query(&contract, "uniswap_v2pair", "token0", ()).await?,
+            token1: self.env.query(&contract, "uniswap_v2pair", "token1", ()).await?,
+            reserve0,
+            reserve1,
+            k_last: self.env.query(&contract, "uniswap_v2pair", "kLast", ()).await?,
+            total_supply: self.env.query(&contract, "uniswap_v2pair", "totalSupply", ()).await?,
+            liquidity: U256::zero(),
+        };
+        fee.liquidity = fee.mint_fee(fee_on);
+        Ok(fee)
+    }
+    pub async fn report(&self) -> DeployResult<()> {
+        let fee_to: Address = self.fee_to().await?;
+        println!("feeTo {}{}", fee_to.as_eip55(), if fee_to.is_zero() { " (protocol fee off)" } else { "" });
+        println!("feeToSetter {}", self.fee_to_setter().await?.as_eip55());
+        Ok(())
+    }
+    pub async fn report_fees(&self) -> DeployResult<()> {
+        let fee_on: bool = !self.fee_to().await?.is_zero();
+        let pairs: Vec<Address> = self.pairs().await?;
+        println!("{} pairs, protocol fee {}", pairs.len(), if fee_on { "on" } else { "off" });
+        for pair in pairs.into_iter() {
+            self.pair_fee(pair, fee_on).await?.report();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pair(reserve0: u64, reserve1: u64, k_last: u64, total_supply: u64) -> PairFee {
+        PairFee {
+            pair: Address::repeat_byte(0xaa),
+            token0: Address::repeat_byte(0x01),
+            token1: Address::repeat_byte(0x02),
+            reserve0: U256::from(reserve0),
+            reserve1: U256::from(reserve1),
+            k_last: U256::from(k_last),
+            total_supply: U256::from(total_supply),
+            liquidity: U256::zero(),
+        }
+    }
+
+    #[test]
+    fn mints_no_fee_without_growth_or_with_the_switch_off() {
+        let grown: PairFee = pair(4_000_000, 1_000_000, 1_000_000_000_000, 1_000_000);
+        assert_eq!(grown.mint_fee(false), U256::zero());
+        // kLast is zero until the first mint or burn with the fee on
+        assert_eq!(pair(4_000_000, 1_000_000, 0, 1_000_000).mint_fee(true), U256::zero());
+        assert_eq!(pair(1_000_000, 1_000_000, 1_000_000_000_000, 1_000_000).mint_fee(true), U256::zero());
+        assert_eq!(pair(999_999, 1_000_000, 1_000_000_000_000, 1_000_000).mint_fee(true), U256::zero());
+    }
+
+    #[test]
+    fn mints_a_sixth_of_the_growth_in_root_k() {
+        // rootK 2,000,000 against rootKLast 1,000,000
+        let mut fee: PairFee = pair(4_000_000, 1_000_000, 1_000_000_000_000, 1_000_000);
+        let (root_k, root_k_last, total_supply) = (U256::from(2_000_000), U256::from(1_000_000), fee.total_supply);
+        let expected: U256 = total_supply * (root_k - root_k_last) / (root_k * 5 + root_k_last);
+        assert_eq!(expected, U256::from(90_909));
+        assert_eq!(fee.mint_fee(true), expected);
+
+        fee.liquidity = expected;
+        // the fee liquidity's share of 1,090,909 in total
+        assert_eq!(fee.owed_amounts(), (U256::from(333_333), U256::from(83_333)));
+    }
+
+    #[test]
+    fn owes_nothing_from_an_empty_pair() {
+        assert_eq!(pair(0, 0, 0, 0).owed_amounts(), (U256::zero(), U256::zero()));
+    }
+}
@@ -0,0 +1,268 @@
+use std::fmt;
+
+use web3::types::{ Address, TransactionReceipt, U256 };
+use web3::ethabi::Token;
+use web3::contract::Contract;
+
+use web3_tools::AsEip55;
+
+use neonevm_sdk::types::Erc20Specs;
+
+use crate::artifact::ArtifactRegistry;
+use crate::liquidity::{ NeonswapEnvironment, Erc20Means, Erc20Token, SwapToken, WethToken, apply_slippage };
+use crate::v1_exchange::{ V1Event, V1Exchange };
+use crate::error::{ DeployError, DeployResult };
+
+// Whole tokens per NEON seeded into a venue that has no liquidity yet. V1 gets more
+// tokens per NEON than V2, so borrowing WETH from the pair and selling it on V1 pays.
+const V2_SEED: (u32, u32) = (1000, 4000);
+const V1_SEED: (u32, u32) = (1, 5);
+const SEED_SLIPPAGE_BPS: u64 = 50;
+const SEED_DEADLINE_SECS: u64 = 1200;
+
+// Which side of the V2 WETH pair ExampleFlashSwap borrows. It sells the loan on V1 and
+// repays the pair in the other asset.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Borrow {
+    Weth,
+    Token,
+}
+
+impl fmt::Display for Borrow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Borrow::Weth => write!(f, "WETH"),
+            Borrow::Token => write!(f, "token"),
+        }
+    }
+}
+
+// UniswapV2Library.getAmountIn, which the example uses to work out the repayment.
+fn v2_amount_in(amount_out: U256, reserve_in: U256, reserve_out: U256) -> DeployResult<U256> {
+    if amount_out.is_zero() || reserve_in.is_zero() || amount_out >= reserve_out {
+        return Err(DeployError::config(format!("cannot borrow {} out of a reserve of {}", amount_out, reserve_out)).for_contract("uniswap_v2pair"));
+    }
+    Ok(reserve_in * amount_out * 1000 / ((reserve_out - amount_out) * 997) + 1)
+}
+
+// UniswapV2Pair.swap arguments for a loan of `amount` of `borrowed`, sent to `to`.
+// uniswapV2Call decodes its V1 slippage bound as abi.decode(data, (uint)).
+fn swap_params(token0: Address, borrowed: Address, amount: U256, to: Address, min_v1_output: U256) -> Vec<Token> {
+    let (amount0_out, amount1_out) = if borrowed == token0 { (amount, U256::zero()) } else { (U256::zero(), amount) };
+    let data: Vec<u8> = web3::ethabi::encode(&[Token::Uint(min_v1_output)]);
+    vec![Token::Uint(amount0_out), Token::Uint(amount1_out), Token::Address(to), Token::Bytes(data)]
+}
+
+fn change(before: U256, after: U256) -> String {
+    if after >= before { format!("+{}", after - before) } else { format!("-{}", before - after) }
+}
+
+pub struct FlashSwapQuote {
+    pub borrow: Borrow,
+    pub amount: U256,
+    // what V1 pays for the loan and what the pair wants back, both in the other asset
+    pub v1_output: U256,
+    pub min_v1_output: U256,
+    pub repay: U256,
+}
+
+impl FlashSwapQuote {
+    pub fn profit(&self) -> Option<U256> {
+        self.v1_output.checked_sub(self.repay).filter(|profit| !profit.is_zero())
+    }
+    fn other(&self) -> &str {
+        match self.borrow {
+            Borrow::Weth => "tokens",
+            Borrow::Token => "wei ETH",
+        }
+    }
+    pub fn report(&self) {
+        println!("Borrow {} {} from the V2 pair", self.amount, if self.borrow == Borrow::Weth { "wei WETH" } else { "tokens" });
+        println!("    V1 pays {} {} for it, at least {}", self.v1_output, self.other(), self.min_v1_output);
+        println!("    the pair wants {} {} back", self.repay, self.other());
+        match self.profit() {
+            Some(profit) => println!("    expected profit {} {}", profit, self.other()),
+            None => println!("    not profitable"),
+        }
+    }
+}
+
+pub struct FlashSwapReport {
+    pub quote: FlashSwapQuote,
+    pub pair: Address,
+    pub eth_before: U256,
+    pub eth_after: U256,
+    pub token_before: U256,
+    pub token_after: U256,
+    pub v1_events: Vec<V1Event>,
+}
+
+impl FlashSwapReport {
+    pub fn report(&self) {
+        println!("Flash swap through pair {}", self.pair.as_eip55());
+        self.quote.report();
+        for event in self.v1_events.iter() {
+            println!("    V1 {}", event);
+        }
+        println!("NEON {} -> {} ({} wei, including gas)", self.eth_before, self.eth_after, change(self.eth_before, self.eth_after));
+        println!("Token {} -> {} ({})", self.token_before, self.token_after, change(self.token_before, self.token_after));
+    }
+}
+
+// Arbitrage between the V2 WETH/token pair and the token's V1 exchange through ExampleFlashSwap.
+pub struct FlashSwap<'a> {
+    env: &'a NeonswapEnvironment,
+    artifacts: &'a ArtifactRegistry,
+    token: Contract<web3::transports::Http>,
+    weth: Address,
+    example: Address,
+}
+
+impl<'a> FlashSwap<'a> {
+    pub fn new(env: &'a NeonswapEnvironment, artifacts: &'a ArtifactRegistry, token: Address) -> DeployResult<FlashSwap<'a>> {
+        Ok(FlashSwap {
+            env,
+            artifacts,
+            token: artifacts.contract(&env.web3, "erc20", token)?,
+            weth: env.contract_address("weth9")?,
+            example: env.contract_address("example")?,
+        })
+    }
+    async fn swap_tokens(&self) -> DeployResult<(SwapToken, SwapToken)> {
+        let token = Erc20Token {
+            specs: Erc20Specs {
+                name: self.env.query(&self.token, "erc20", "name", ()).await?,
+                symbol: self.env.query(&self.token, "erc20", "symbol", ()).await?,
+                decimals: self.env.query(&self.token, "erc20", "decimals", ()).await?,
+            },
+            eth_address: self.token.address().into(),
+            means: Erc20Means::Origin,
+        };
+        Ok((SwapToken::Weth(WethToken::new(&self.weth.as_eip55())), SwapToken::Erc20(token)))
+    }
+    // Seeds whichever venue has no liquidity from the provider role.
+    pub async fn ensure_liquidity(&self, provider: &str) -> DeployResult<V1Exchange<'a>> {
+
+        let (weth, token) = self.swap_tokens().await?;
+        let (reserve_token, reserve_weth) = self.env.pair_reserves(self.token.address(), self.weth).await?;
+        if reserve_token.is_zero() || reserve_weth.is_zero() {
+            let amount_weth: U256 = U256::from(weth.expand_from_uint(V2_SEED.0));
+            let amount_token: U256 = U256::from(token.expand_from_uint(V2_SEED.1));
+            self.env.create_pair_add_liquidity(provider, &weth, &token, amount_weth, amount_token, SEED_SLIPPAGE_BPS).await?;
+        }
+
+        let factory: Contract<web3::transports::Http> = self.artifacts.contract(&self.env.web3, "uniswap_v1factory", self.env.contract_address("uniswap_v1factory")?)?;
+        let exchange: Address = self.env.query(&factory, "uniswap_v1factory", "getExchange", self.token.address()).await?;
+        if exchange.is_zero() {
+            self.env.send_call(provider, "create_exchange", &factory, "createExchange", self.token.address())
+                .await
+                .map_err(|e| e.for_contract("uniswap_v1factory"))?;
+        }
+
+        let v1: V1Exchange = V1Exchange::for_token(self.env, self.artifacts, self.token.address()).await?;
+        if v1.total_supply().await?.is_zero() {
+            let amount_eth: U256 = U256::from(weth.expand_from_uint(V1_SEED.0));
+            let amount_token: U256 = U256::from(token.expand_from_uint(V1_SEED.1));
+            v1.approve_token(provider, amount_token).await?;
+            let deadline: U256 = v1.deadline(SEED_DEADLINE_SECS).await?;
+            v1.add_liquidity(provider, amount_eth, U256::zero(), amount_token, deadline).await?.report();
+        }
+        Ok(v1)
+    }
+    // The V2 side is priced at the pair's reserves before the swap, which is what
+    // getAmountsIn sees inside uniswapV2Call.
+    pub async fn quote(&self, v1: &V1Exchange<'_>, borrow: Borrow, amount: U256, slippage_bps: u64) -> DeployResult<FlashSwapQuote> {
+        let (reserve_token, reserve_weth) = self.env.pair_reserves(self.token.address(), self.weth).await?;
+        let (v1_output, repay) =
+            match borrow {
+                Borrow::Weth => (v1.get_eth_to_token_input_price(amount).await?, v2_amount_in(amount, reserve_token, reserve_weth)?),
+                Borrow::Token => (v1.get_token_to_eth_input_price(amount).await?, v2_amount_in(amount, reserve_weth, reserve_token)?),
+            };
+        Ok(FlashSwapQuote { borrow, amount, v1_output, min_v1_output: apply_slippage(v1_output, slippage_bps)?, repay })
+    }
+    async fn balances(&self, owner: Address) -> DeployResult<(U256, U256)> {
+        let eth: U256 = self.env.web3.eth().balance(owner, None).await.map_err(DeployError::rpc)?;
+        Ok((eth, self.env.query(&self.token, "erc20", "balanceOf", owner).await?))
+    }
+    // The role calls the pair's swap itself, ExampleFlashSwap sends the profit back to it.
+    pub async fn execute(&self, role: &str, provider: &str, borrow: Borrow, amount: U256, slippage_bps: u64) -> DeployResult<FlashSwapReport> {
+
+        let v1: V1Exchange = self.ensure_liquidity(provider).await?;
+        let quote: FlashSwapQuote = self.quote(&v1, borrow, amount, slippage_bps).await?;
+        if quote.profit().is_none() {
+            quote.report();
+            return Err(DeployError::config(format!("V1 pays {} for the loan but the pair wants {} back, ExampleFlashSwap would revert", quote.v1_output, quote.repay)));
+        }
+
+        let (weth, token) = self.swap_tokens().await?;
+        let pair_address: Address = self.env.get_pair(&weth, &token).await?;
+        let pair: Contract<web3::transports::Http> = self.artifacts.contract(&self.env.web3, "uniswap_v2pair", pair_address)?;
+        let token0: Address = self.env.query(&pair, "uniswap_v2pair", "token0", ()).await?;
+        let borrowed: Address = if borrow == Borrow::Weth { self.weth } else { self.token.address() };
+        let params: Vec<Token> = swap_params(token0, borrowed, amount, self.example, quote.min_v1_output);
+
+        let owner: Address = self.env.signers.get(role)?.address;
+        let (eth_before, token_before) = self.balances(owner).await?;
+        let receipt: TransactionReceipt =
+            self.env.send_call(role, "flash_swap", &pair, "swap", &params[..])
+                .await
+                .map_err(|e| e.for_contract("uniswap_v2pair"))?;
+        let (eth_after, token_after) = self.balances(owner).await?;
+
+        Ok(FlashSwapReport {
+            quote,
+            pair: pair_address,
+            eth_before,
+            eth_after,
+            token_before,
+            token_after,
+            v1_events: v1.decode_events(&receipt)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn amount_in(amount_out: u64, reserve_in: u64, reserve_out: u64) -> DeployResult<U256> {
+        v2_amount_in(U256::from(amount_out), U256::from(reserve_in), U256::from(reserve_out))
+    }
+
+    #[test]
+    fn matches_uniswap_v2_library_get_amount_in() {
+        // the UniswapV2Library test vector
+        assert_eq!(amount_in(1, 100, 100).unwrap(), U256::from(2));
+        assert_eq!(amount_in(400, 1000, 4000).unwrap(), U256::from(112));
+        let ether: U256 = U256::exp10(18);
+        assert_eq!(v2_amount_in(ether, ether * 5, ether * 10).unwrap(), U256::from(557_227_237_267_357_629u64));
+    }
+
+    #[test]
+    fn rejects_what_get_amount_in_reverts_on() {
+        assert!(amount_in(0, 100, 100).is_err());
+        assert!(amount_in(1, 0, 100).is_err());
+        assert!(amount_in(100, 100, 100).is_err());
+        assert!(amount_in(101, 100, 100).is_err());
+    }
+
+    #[test]
+    fn borrows_from_the_side_the_pair_sorts_the_loan_into() {
+        let (weth, token, example) = (Address::repeat_byte(0x01), Address::repeat_byte(0x02), Address::repeat_byte(0xe0));
+        let (amount, min_v1_output) = (U256::from(1000), U256::from(5000));
+        let data: Vec<u8> = [vec![0; 30], vec![0x13, 0x88]].concat();
+
+        // WETH sorts first here, so a WETH loan is amount0Out and a token loan amount1Out
+        assert_eq!(
+            swap_params(weth, weth, amount, example, min_v1_output),
+            vec![Token::Uint(amount), Token::Uint(U256::zero()), Token::Address(example), Token::Bytes(data.clone())],
+        );
+        assert_eq!(
+            swap_params(weth, token, amount, example, min_v1_output),
+            vec![Token::Uint(U256::zero()), Token::Uint(amount), Token::Address(example), Token::Bytes(data.clone())],
+        );
+        // and the other way round once the token sorts first
+        assert_eq!(swap_params(token, weth, amount, example, min_v1_output)[..2], [Token::Uint(U256::zero()), Token::Uint(amount)]);
+        assert_eq!(swap_params(token, token, amount, example, min_v1_output)[..2], [Token::Uint(amount), Token::Uint(U256::zero())]);
+    }
+}
@@ -0,0 +1,240 @@
+use std::fmt;
+
+use web3::types::{ Address, BlockNumber, Bytes, CallRequest, TransactionParameters, TransactionReceipt, U256, U64 };
+use web3::contract::Contract;
+use web3::contract::tokens::Tokenize;
+
+use crate::config::{ FeeStrategy, GasLimit, GasSettings };
+use crate::error::{ DeployError, DeployResult, revert_reason };
+
+#[derive(Clone, Copy, Debug)]
+pub enum Fees {
+    Legacy { gas_price: U256 },
+    Eip1559 { max_fee_per_gas: U256, max_priority_fee_per_gas: U256 },
+}
+
+impl Fees {
+    // The most a unit of gas can cost, used to bound the cost of a transaction.
+    pub fn max_price(&self) -> U256 {
+        match self {
+            Fees::Legacy { gas_price }            => *gas_price,
+            Fees::Eip1559 { max_fee_per_gas, .. } => *max_fee_per_gas,
+        }
+    }
+}
+
+impl fmt::Display for Fees {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Fees::Legacy { gas_price } => write!(f, "gas price {}", gas_price),
+            Fees::Eip1559 { max_fee_per_gas, max_priority_fee_per_gas } =>
+                write!(f, "max fee {} (priority {})", max_fee_per_gas, max_priority_fee_per_gas),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct GasPlan {
+    pub operation: String,
+    pub gas: U256,
+    pub estimate: Option<U256>,
+    pub fees: Fees,
+}
+
+impl GasPlan {
+    pub fn transaction(&self, to: Option<Address>, data: Bytes) -> TransactionParameters {
+        let tx = TransactionParameters {
+            to,
+            gas: self.gas,
+            data,
+            ..TransactionParameters::default()
+        };
+        match self.fees {
+            Fees::Legacy { gas_price } => TransactionParameters { gas_price: Some(gas_price), ..tx },
+            Fees::Eip1559 { max_fee_per_gas, max_priority_fee_per_gas } => {
+                TransactionParameters {
+                    transaction_type: Some(U64::from(2)),
+                    max_fee_per_gas: Some(max_fee_per_gas),
+                    max_priority_fee_per_gas: Some(max_priority_fee_per_gas),
+                    ..tx
+                }
+            },
+        }
+    }
+    pub fn report(&self, receipt: &TransactionReceipt) {
+        let used: U256 =
+            match receipt.gas_used {
+                Some(used) => used,
+                None => return,
+            };
+        println!("    {}", self.usage(used));
+    }
+    // A fixed limit is never estimated, so it is compared to the limit and says so.
+    fn usage(&self, used: U256) -> String {
+        let percent = |reference: U256| if reference.is_zero() { U256::zero() } else { used * 100 / reference };
+        match self.estimate {
+            Some(estimate) =>
+                format!("{}: gas used {} of {} estimated ({}%)", self.operation, used, estimate, percent(estimate)),
+            None =>
+                format!("{}: gas used {} of the fixed limit {} ({}%), not estimated", self.operation, used, self.gas, percent(self.gas)),
+        }
+    }
+}
+
+// Scales by a float multiplier in thousandths, which is plenty for fee margins.
+fn scale(value: U256, multiplier: f64) -> U256 {
+    value * U256::from((multiplier * 1000.0).round() as u64) / U256::from(1000)
+}
+
+// Blocks without a reward at the percentile were already dropped, none at all means no tip.
+fn average(values: &[U256]) -> U256 {
+    if values.is_empty() {
+        U256::zero()
+    } else {
+        values.iter().fold(U256::zero(), |sum, v| sum + v) / U256::from(values.len())
+    }
+}
+
+fn estimate_error(error: web3::Error) -> DeployError {
+    match error {
+        web3::Error::Rpc(_) => DeployError::reverted(revert_reason(&error)),
+        _ => DeployError::rpc(error),
+    }
+}
+
+pub fn call_request(contract: &Contract<web3::transports::Http>, method: &str, params: impl Tokenize, from: Address) -> DeployResult<CallRequest> {
+    let data: Vec<u8> =
+        contract.abi()
+            .function(method)
+            .and_then(|f| f.encode_input(&params.into_tokens()))
+            .map_err(|e| DeployError::artifact(format!("cannot encode {}: {}", method, e)))?;
+    Ok(CallRequest {
+        from: Some(from),
+        to: Some(contract.address()),
+        data: Some(Bytes(data)),
+        ..CallRequest::default()
+    })
+}
+
+pub struct GasStrategy<'a> {
+    web3: &'a web3::Web3<web3::transports::Http>,
+    settings: &'a GasSettings,
+}
+
+impl<'a> GasStrategy<'a> {
+    pub fn new(web3: &'a web3::Web3<web3::transports::Http>, settings: &'a GasSettings) -> Self {
+        GasStrategy { web3, settings }
+    }
+    pub async fn fees(&self, operation: &str) -> DeployResult<Fees> {
+        match self.settings.fee(operation) {
+            FeeStrategy::Fixed { price } => Ok(Fees::Legacy { gas_price: U256::from(*price) }),
+            FeeStrategy::GasPrice { multiplier } => {
+                let gas_price: U256 = self.web3.eth().gas_price().await.map_err(DeployError::rpc)?;
+                Ok(Fees::Legacy { gas_price: scale(gas_price, *multiplier) })
+            },
+            FeeStrategy::FeeHistory { blocks, percentile, base_fee_multiplier } => {
+                let history =
+                    self.web3.eth().fee_history(U256::from(*blocks), BlockNumber::Latest, Some(vec![*percentile]))
+                        .await
+                        .map_err(DeployError::rpc)?;
+                // The last entry is the base fee of the block after the newest one.
+                let base_fee: U256 =
+                    history.base_fee_per_gas
+                        .last()
+                        .copied()
+                        .ok_or_else(|| DeployError::rpc("eth_feeHistory returned no base fee"))?;
+                let rewards: Vec<U256> =
+                    history.reward
+                        .unwrap_or_default()
+                        .into_iter()
+                        .filter_map(|block| block.first().copied())
+                        .collect();
+                let priority_fee: U256 = average(&rewards);
+                Ok(Fees::Eip1559 {
+                    max_fee_per_gas: scale(base_fee, *base_fee_multiplier) + priority_fee,
+                    max_priority_fee_per_gas: priority_fee,
+                })
+            },
+        }
+    }
+    pub async fn estimate(&self, request: CallRequest) -> DeployResult<U256> {
+        self.web3.eth().estimate_gas(request, None).await.map_err(estimate_error)
+    }
+    pub async fn plan(&self, operation: &str, request: CallRequest) -> DeployResult<GasPlan> {
+        let (gas, estimate): (U256, Option<U256>) =
+            match self.settings.limit(operation) {
+                GasLimit::Fixed { gas } => (U256::from(*gas), None),
+                GasLimit::Estimate { margin_percent } => {
+                    let estimate: U256 = self.estimate(request).await?;
+                    (estimate * (100 + margin_percent) / 100, Some(estimate))
+                },
+            };
+        Ok(GasPlan {
+            operation: operation.to_string(),
+            gas,
+            estimate,
+            fees: self.fees(operation).await?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use crate::test_node::TestNode;
+
+    #[test]
+    fn scales_in_thousandths() {
+        assert_eq!(scale(U256::from(1000), 1.0), U256::from(1000));
+        assert_eq!(scale(U256::from(1000), 1.125), U256::from(1125));
+        assert_eq!(scale(U256::from(1000), 0.5), U256::from(500));
+        // Below a thousandth rounds to the nearest one.
+        assert_eq!(scale(U256::from(1000), 1.0004), U256::from(1000));
+        assert_eq!(scale(U256::from(1000), 1.0006), U256::from(1001));
+    }
+
+    #[test]
+    fn averages_rewards() {
+        assert_eq!(average(&[]), U256::zero());
+        assert_eq!(average(&[U256::from(7)]), U256::from(7));
+        assert_eq!(average(&[U256::from(1), U256::from(2), U256::from(4)]), U256::from(2));
+    }
+
+    #[test]
+    fn reports_a_fixed_limit_as_not_estimated() {
+        let plan = GasPlan { operation: "swap".to_string(), gas: U256::from(200), estimate: None, fees: Fees::Legacy { gas_price: U256::one() } };
+        assert_eq!(plan.usage(U256::from(50)), "swap: gas used 50 of the fixed limit 200 (25%), not estimated");
+        let plan = GasPlan { estimate: Some(U256::from(100)), ..plan };
+        assert_eq!(plan.usage(U256::from(50)), "swap: gas used 50 of 100 estimated (50%)");
+    }
+
+    #[tokio::test]
+    async fn builds_eip1559_fees_from_the_fee_history() {
+        let node = TestNode::start(|method, _| match method {
+            "eth_feeHistory" => Ok(json!({
+                "oldestBlock": "0x10",
+                "baseFeePerGas": ["0x64", "0x6e", "0x78"],
+                "gasUsedRatio": [0.5, 0.5],
+                // the empty block has no reward and does not drag the average down
+                "reward": [["0xa"], ["0x14"], []],
+            })),
+            _ => Err(json!({ "code": -32601, "message": "method not found" })),
+        });
+        let web3 = node.web3();
+        let settings: GasSettings = serde_json::from_value(json!({
+            "limit": { "mode": "fixed", "gas": 3000000 },
+            "fee": { "mode": "fee_history", "blocks": 3, "percentile": 50.0, "base_fee_multiplier": 2.0 }
+        })).unwrap();
+        let fees: Fees = GasStrategy::new(&web3, &settings).fees("swap").await.unwrap();
+        match fees {
+            Fees::Eip1559 { max_fee_per_gas, max_priority_fee_per_gas } => {
+                assert_eq!(max_priority_fee_per_gas, U256::from(15));
+                // twice the next block's base fee of 120, plus the tip
+                assert_eq!(max_fee_per_gas, U256::from(255));
+            },
+            other => panic!("expected EIP-1559 fees, got {:?}", other),
+        }
+        assert_eq!(node.calls("eth_feeHistory")[0], json!(["0x3", "latest", [50.0]]));
+    }
+}
@@ -0,0 +1,357 @@
+use std::collections::HashMap;
+use std::fmt;
+use serde::{ Deserialize };
+
+use web3::types::{ Address, U256 };
+use web3::ethabi::{ Function, Token };
+use web3::contract::Contract;
+use web3::contract::tokens::{ Detokenize, Tokenize };
+
+use web3_tools::AsEip55;
+
+use crate::artifact::{ Artifact, ArtifactRegistry };
+use crate::address_book::AddressBook;
+use crate::manifest::{ Manifest, ManifestArg, StepAction };
+use crate::liquidity::NeonswapEnvironment;
+use crate::signers::DEPLOYER;
+use crate::error::{ DeployError, DeployResult, ErrorKind };
+
+struct WiringCheck {
+    label: &'static str,
+    expected: Address,
+    actual: Address,
+}
+
+impl WiringCheck {
+    fn passed(&self) -> bool {
+        self.expected == self.actual
+    }
+    fn report(&self) {
+        let result: &str = if self.passed() { "pass" } else { "FAIL" };
+        println!("{:<32} {:<44} {:<44}  {}", self.label, self.expected.as_eip55(), self.actual.as_eip55(), result);
+    }
+}
+
+// Checks that Timelock, GovernorAlpha and Uni point at each other the way the Uniswap
+// governance expects, then, when the profile asks for it, hands control of the V2
+// factory fees to the Timelock. Nothing is handed over while the wiring itself is wrong.
+pub async fn bootstrap_governance(env: &NeonswapEnvironment, artifacts: &ArtifactRegistry, hand_over_fee_setter: bool) -> DeployResult<()> {
+
+    let from: Address = env.signers.deployer().address;
+    let uni_address: Address = env.contract_address("uni")?;
+    let timelock_address: Address = env.contract_address("timelock")?;
+    let governor_alpha_address: Address = env.contract_address("governor_alpha")?;
+    let factory_address: Address = env.contract_address("uniswap_v2factory")?;
+
+    let uni: Contract<web3::transports::Http> = artifacts.contract(&env.web3, "uni", uni_address)?;
+    let timelock: Contract<web3::transports::Http> = artifacts.contract(&env.web3, "timelock", timelock_address)?;
+    let governor_alpha: Contract<web3::transports::Http> = artifacts.contract(&env.web3, "governor_alpha", governor_alpha_address)?;
+    let factory: Contract<web3::transports::Http> = artifacts.contract(&env.web3, "uniswap_v2factory", factory_address)?;
+
+    println!("{:<32} {:<44} {:<44}  {}", "Governance Check", "Expected", "Actual", "Result");
+
+    let checks: Vec<WiringCheck> = vec![
+        WiringCheck { label: "Timelock.admin", expected: governor_alpha_address, actual: env.query(&timelock, "timelock", "admin", ()).await? },
+        WiringCheck { label: "GovernorAlpha.timelock", expected: timelock_address, actual: env.query(&governor_alpha, "governor_alpha", "timelock", ()).await? },
+        WiringCheck { label: "GovernorAlpha.uni", expected: uni_address, actual: env.query(&governor_alpha, "governor_alpha", "uni", ()).await? },
+        WiringCheck { label: "Uni.minter", expected: timelock_address, actual: env.query(&uni, "uni", "minter", ()).await? },
+    ];
+    checks.iter().for_each(|check| check.report());
+
+    if checks.iter().any(|check| !check.passed()) {
+        let failed: Vec<&str> = checks.iter().filter(|check| !check.passed()).map(|check| check.label).collect();
+        return Err(DeployError::new(ErrorKind::Verification(format!("governance wiring does not match: {}", failed.join(", ")))));
+    }
+
+    // Only the deployer can hand the fee setter over; any other holder is reported as is.
+    let mut fee_to_setter: Address = env.query(&factory, "uniswap_v2factory", "feeToSetter", ()).await?;
+    if !hand_over_fee_setter {
+        println!("UniswapV2Factory.feeToSetter stays with {}, set governance.hand_over_fee_setter to give it to the Timelock", fee_to_setter.as_eip55());
+        return Ok(());
+    }
+    if fee_to_setter == from {
+        env.send_call(DEPLOYER, "set_fee_to_setter", &factory, "setFeeToSetter", timelock_address)
+            .await
+            .map_err(|e| e.for_contract("uniswap_v2factory"))?;
+        println!("Handed UniswapV2Factory.feeToSetter over to the Timelock");
+        fee_to_setter = env.query(&factory, "uniswap_v2factory", "feeToSetter", ()).await?;
+    }
+    let handover = WiringCheck { label: "UniswapV2Factory.feeToSetter", expected: timelock_address, actual: fee_to_setter };
+    handover.report();
+    if !handover.passed() {
+        return Err(DeployError::new(ErrorKind::Verification(format!("{} is held by {}, not the Timelock", handover.label, handover.actual.as_eip55()))));
+    }
+    Ok(())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProposalState {
+    Pending,
+    Active,
+    Canceled,
+    Defeated,
+    Succeeded,
+    Queued,
+    Expired,
+    Executed,
+}
+
+impl ProposalState {
+    fn from_u8(value: u8) -> DeployResult<ProposalState> {
+        let states: [ProposalState; 8] = [
+            ProposalState::Pending, ProposalState::Active, ProposalState::Canceled, ProposalState::Defeated,
+            ProposalState::Succeeded, ProposalState::Queued, ProposalState::Expired, ProposalState::Executed,
+        ];
+        states.get(value as usize)
+            .copied()
+            .ok_or_else(|| DeployError::rpc(format!("unknown proposal state {}", value)).for_contract("governor_alpha"))
+    }
+}
+
+impl fmt::Display for ProposalState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+// One call the Timelock makes once the proposal passes. `target` is an address book
+// name, `method` is looked up in that contract's artifact.
+#[derive(Clone, Debug)]
+#[derive(Deserialize)]
+pub struct ProposalAction {
+    pub target: String,
+    pub method: String,
+    #[serde(default)]
+    pub args: Vec<ManifestArg>,
+    // wei, sent along with the call by the Timelock
+    #[serde(default)]
+    pub value: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+#[derive(Deserialize)]
+pub struct ProposalSpec {
+    pub description: String,
+    pub actions: Vec<ProposalAction>,
+}
+
+pub fn read_proposal(path: &str) -> DeployResult<ProposalSpec> {
+    let file = std::fs::File::open(path).map_err(|e| DeployError::config(format!("failed to open proposal {}: {}", path, e)))?;
+    let reader = std::io::BufReader::new(file);
+    serde_json::from_reader(reader).map_err(|e| DeployError::config(format!("proposal {} is malformed: {}", path, e)))
+}
+
+// The parts GovernorAlpha.propose takes for one action: the signature is the plain
+// `name(types)` form and the calldata carries the arguments without a selector.
+pub struct EncodedAction {
+    pub target: Address,
+    pub value: U256,
+    pub signature: String,
+    pub calldata: Vec<u8>,
+}
+
+pub fn encode_action(action: &ProposalAction, manifest: &Manifest, artifacts: &ArtifactRegistry, address_book: &AddressBook, deployer: Address) -> DeployResult<EncodedAction> {
+
+    let artifact_name: &str =
+        match manifest.step(&action.target).map(|step| &step.action) {
+            Some(StepAction::Deploy { artifact, .. }) => artifact,
+            _ => return Err(DeployError::config(format!("proposal target '{}' is not a contract deployed by the manifest", action.target))),
+        };
+    let target: Address =
+        address_book.address(&action.target)
+            .ok_or_else(|| DeployError::address_book(format!("contract '{}' is not in the address book", action.target)))?;
+    let artifact: &Artifact = artifacts.get(artifact_name)?;
+    let function: &Function =
+        artifact.abi.function(&action.method)
+            .map_err(|_| DeployError::artifact(format!("{} has no method {}", artifact_name, action.method)))?;
+
+    if action.args.len() != function.inputs.len() {
+        return Err(DeployError::config(format!("{}.{} takes {} arguments, the proposal gives {}", action.target, action.method, function.inputs.len(), action.args.len())));
+    }
+    let addresses: HashMap<String,Address> = address_book.contracts.iter().map(|(name, record)| (name.clone(), record.address)).collect();
+    let mut tokens: Vec<Token> = Vec::new();
+    for (arg, param) in action.args.iter().zip(function.inputs.iter()) {
+        let token: Token = arg.to_token(deployer, &addresses)?;
+        if !token.type_check(&param.kind) {
+            return Err(DeployError::config(format!("argument {} of {}.{} must be {}", param.name, action.target, action.method, param.kind)));
+        }
+        tokens.push(token);
+    }
+
+    let value: U256 =
+        match &action.value {
+            Some(value) => U256::from_dec_str(value).map_err(|_| DeployError::config(format!("value '{}' is not a decimal integer", value)))?,
+            None => U256::zero(),
+        };
+    let types: Vec<String> = function.inputs.iter().map(|param| param.kind.to_string()).collect();
+    Ok(EncodedAction {
+        target,
+        value,
+        signature: format!("{}({})", function.name, types.join(",")),
+        calldata: web3::ethabi::encode(&tokens),
+    })
+}
+
+pub struct ProposalStatus {
+    pub id: U256,
+    pub proposer: Address,
+    pub state: ProposalState,
+    pub eta: U256,
+    pub start_block: U256,
+    pub end_block: U256,
+    pub for_votes: U256,
+    pub against_votes: U256,
+    pub quorum_votes: U256,
+}
+
+impl ProposalStatus {
+    pub fn report(&self) {
+        let quorum: &str = if self.for_votes >= self.quorum_votes { "reached" } else { "not reached" };
+        println!("Proposal {} by {}: {}", self.id, self.proposer.as_eip55(), self.state);
+        println!("    votes for {} against {}, quorum {} ({})", self.for_votes, self.against_votes, self.quorum_votes, quorum);
+        println!("    voting from block {} to {}", self.start_block, self.end_block);
+        if !self.eta.is_zero() {
+            println!("    executable from {}", self.eta);
+        }
+    }
+}
+
+pub struct VoteReceipt {
+    pub has_voted: bool,
+    pub support: bool,
+    pub votes: U256,
+}
+
+// Typed access to GovernorAlpha. Transactions are sent by the given role through the
+// environment, so they share its gas settings, nonces and journal.
+pub struct Governor<'a> {
+    env: &'a NeonswapEnvironment,
+    contract: Contract<web3::transports::Http>,
+}
+
+impl<'a> Governor<'a> {
+    pub fn new(env: &'a NeonswapEnvironment, artifacts: &ArtifactRegistry) -> DeployResult<Governor<'a>> {
+        let contract: Contract<web3::transports::Http> = artifacts.contract(&env.web3, "governor_alpha", env.contract_address("governor_alpha")?)?;
+        Ok(Governor { env, contract })
+    }
+    async fn query<R: Detokenize>(&self, method: &str, params: impl Tokenize) -> DeployResult<R> {
+        self.env.query(&self.contract, "governor_alpha", method, params).await
+    }
+    async fn send(&self, role: &str, operation: &str, method: &str, params: impl Tokenize) -> DeployResult<()> {
+        self.env.send_call(role, operation, &self.contract, method, params)
+            .await
+            .map(|_| ())
+            .map_err(|e| e.for_contract("governor_alpha"))
+    }
+    pub async fn propose(&self, role: &str, spec: &ProposalSpec, manifest: &Manifest, artifacts: &ArtifactRegistry) -> DeployResult<U256> {
+
+        let deployer: Address = self.env.signers.deployer().address;
+        let mut actions: Vec<EncodedAction> = Vec::new();
+        for action in spec.actions.iter() {
+            actions.push(encode_action(action, manifest, artifacts, &self.env.address_book, deployer)?);
+        }
+        let params: Vec<Token> = vec![
+            Token::Array(actions.iter().map(|a| Token::Address(a.target)).collect()),
+            Token::Array(actions.iter().map(|a| Token::Uint(a.value)).collect()),
+            Token::Array(actions.iter().map(|a| Token::String(a.signature.clone())).collect()),
+            Token::Array(actions.iter().map(|a| Token::Bytes(a.calldata.clone())).collect()),
+            Token::String(spec.description.clone()),
+        ];
+        for action in actions.iter() {
+            println!("    {} {} value {}", action.target.as_eip55(), action.signature, action.value);
+        }
+        self.send(role, "governance_propose", "propose", &params[..]).await?;
+
+        let proposer: Address = self.env.signers.get(role)?.address;
+        self.query("latestProposalIds", proposer).await
+    }
+    pub async fn cast_vote(&self, role: &str, id: U256, support: bool) -> DeployResult<()> {
+        self.send(role, "governance_vote", "castVote", (id, support)).await
+    }
+    pub async fn queue(&self, role: &str, id: U256) -> DeployResult<()> {
+        self.send(role, "governance_queue", "queue", id).await
+    }
+    // GovernorAlpha forwards the action values to the Timelock, so they are paid here.
+    pub async fn execute(&self, role: &str, id: U256) -> DeployResult<()> {
+        let (_, values, _, _): (Token, Vec<U256>, Token, Token) = self.query("getActions", id).await?;
+        let value: U256 = values.iter().fold(U256::zero(), |total, value| total + value);
+        self.env.send_payable(role, "governance_execute", &self.contract, "execute", id, value)
+            .await
+            .map(|_| ())
+            .map_err(|e| e.for_contract("governor_alpha"))
+    }
+    pub async fn cancel(&self, role: &str, id: U256) -> DeployResult<()> {
+        self.send(role, "governance_cancel", "cancel", id).await
+    }
+    pub async fn state(&self, id: U256) -> DeployResult<ProposalState> {
+        let state: u8 = self.query("state", id).await?;
+        ProposalState::from_u8(state)
+    }
+    pub async fn status(&self, id: U256) -> DeployResult<ProposalStatus> {
+        let (id, proposer, eta, start_block, end_block, for_votes, against_votes, _, _): (U256, Address, U256, U256, U256, U256, U256, bool, bool) =
+            self.query("proposals", id).await?;
+        Ok(ProposalStatus {
+            id,
+            proposer,
+            state: self.state(id).await?,
+            eta,
+            start_block,
+            end_block,
+            for_votes,
+            against_votes,
+            quorum_votes: self.query("quorumVotes", ()).await?,
+        })
+    }
+    pub async fn receipt(&self, id: U256, voter: Address) -> DeployResult<VoteReceipt> {
+        let receipt: Token = self.query("getReceipt", (id, voter)).await?;
+        match receipt {
+            Token::Tuple(fields) => {
+                match fields.as_slice() {
+                    [Token::Bool(has_voted), Token::Bool(support), Token::Uint(votes)] => Ok(VoteReceipt { has_voted: *has_voted, support: *support, votes: *votes }),
+                    _ => Err(DeployError::rpc(format!("unexpected receipt {:?}", fields)).for_contract("governor_alpha")),
+                }
+            },
+            other => Err(DeployError::rpc(format!("unexpected receipt {:?}", other)).for_contract("governor_alpha")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+    use crate::artifact::tests::registry;
+    use crate::address_book::tests::address_book;
+
+    #[test]
+    fn encodes_the_set_fee_to_proposal() {
+        let proposal: ProposalSpec = read_proposal(concat!(env!("CARGO_MANIFEST_DIR"), "/proposals/set_fee_to.json")).unwrap();
+        let manifest: Manifest = serde_json::from_value(json!({
+            "steps": [ { "name": "uniswap_v2factory", "title": "UniswapV2Factory", "deploy": { "artifact": "uniswap_v2factory", "args": [ "deployer" ] } } ]
+        })).unwrap();
+        let artifacts: ArtifactRegistry = registry(vec![(
+            "uniswap_v2factory",
+            json!({
+                "abi": [ { "type": "function", "name": "setFeeTo", "inputs": [ { "name": "_feeTo", "type": "address" } ], "outputs": [], "stateMutability": "nonpayable" } ],
+                "bytecode": "0x6080",
+                "deployedBytecode": "0x6080",
+            }),
+        )]);
+        let factory: Address = Address::repeat_byte(0xfa);
+        let deployer: Address = Address::repeat_byte(0xde);
+
+        let actions: Vec<EncodedAction> =
+            proposal.actions.iter()
+                .map(|action| encode_action(action, &manifest, &artifacts, &address_book(&[("uniswap_v2factory", factory)]), deployer))
+                .collect::<DeployResult<_>>()
+                .unwrap();
+
+        assert_eq!(actions.len(), 1);
+        assert_eq!(actions[0].target, factory);
+        assert_eq!(actions[0].value, U256::zero());
+        assert_eq!(actions[0].signature, "setFeeTo(address)");
+        // the bare argument, GovernorAlpha prepends the selector of the signature itself
+        assert_eq!(actions[0].calldata.len(), 32);
+        assert_eq!(actions[0].calldata, [vec![0; 12], deployer.as_bytes().to_vec()].concat());
+    }
+}
@@ -0,0 +1,86 @@
+use std::io::Write;
+use std::time::SystemTime;
+use serde::{ Serialize };
+
+use web3::types::{ TransactionParameters, H256, U256, U64 };
+
+#[derive(Clone, Debug)]
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum JournalEvent {
+    Submitted {
+        attempt: u32,
+        replaces: Option<H256>,
+        gas: U256,
+        gas_price: Option<U256>,
+        max_fee_per_gas: Option<U256>,
+        max_priority_fee_per_gas: Option<U256>,
+    },
+    Rebroadcast,
+    GapFilled { dropped: H256 },
+    TimedOut { waited_secs: u64 },
+    Mined { block: Option<U64>, status: Option<U64>, gas_used: Option<U256> },
+    Abandoned { attempts: u32 },
+    Failed { error: String },
+}
+
+impl JournalEvent {
+    pub fn submitted(attempt: u32, replaces: Option<H256>, tx: &TransactionParameters) -> Self {
+        JournalEvent::Submitted {
+            attempt,
+            replaces,
+            gas: tx.gas,
+            gas_price: tx.gas_price,
+            max_fee_per_gas: tx.max_fee_per_gas,
+            max_priority_fee_per_gas: tx.max_priority_fee_per_gas,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+#[derive(Serialize)]
+pub struct JournalEntry<'a> {
+    pub time: u64,
+    pub operation: &'a str,
+    pub nonce: U256,
+    pub tx_hash: Option<H256>,
+    #[serde(flatten)]
+    pub event: JournalEvent,
+}
+
+// Append-only JSON lines log of every transaction attempt, one line per event.
+pub struct Journal {
+    path: String,
+}
+
+impl Journal {
+    pub fn new(path: &str) -> Self {
+        Journal { path: path.to_string() }
+    }
+    // A journal that cannot be written must not abandon transactions already in flight,
+    // so failures are reported and otherwise ignored.
+    pub fn record(&self, operation: &str, nonce: U256, tx_hash: Option<H256>, event: JournalEvent) {
+
+        let entry = JournalEntry {
+            time: SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+            operation,
+            nonce,
+            tx_hash,
+            event,
+        };
+        let written =
+            serde_json::to_string(&entry)
+                .map_err(|e| e.to_string())
+                .and_then(|line| {
+                    std::fs::OpenOptions::new()
+                        .create(true)
+                        .append(true)
+                        .open(&self.path)
+                        .and_then(|mut file| writeln!(file, "{}", line))
+                        .map_err(|e| e.to_string())
+                });
+        if let Err(error) = written {
+            println!("Failed to write journal {}: {}", self.path, error);
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use zeroize::{ Zeroize, Zeroizing };
+use ethers_signers::{ LocalWallet, MnemonicBuilder, coins_bip39::English };
+
+use crate::config::KeySource;
+use crate::error::{ DeployError, DeployResult, ErrorKind };
+
+fn key_error(message: String) -> DeployError {
+    DeployError::new(ErrorKind::Signing(message))
+}
+
+#[cfg(unix)]
+fn warn_if_world_readable(path: &str) {
+    use std::os::unix::fs::PermissionsExt;
+    if let Ok(metadata) = std::fs::metadata(path) {
+        if metadata.permissions().mode() & 0o004 != 0 {
+            println!("Warning: key material in {} is readable by every user, consider `chmod 600 {}`", path, path);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn warn_if_world_readable(_path: &str) {}
+
+fn read_secret_file(path: &str) -> DeployResult<Zeroizing<String>> {
+    warn_if_world_readable(path);
+    std::fs::read_to_string(path)
+        .map(Zeroizing::new)
+        .map_err(|e| DeployError::config(format!("failed to read key file {}: {}", path, e)))
+}
+
+fn read_secret_env(name: &str) -> DeployResult<Zeroizing<String>> {
+    std::env::var(name)
+        .map(Zeroizing::new)
+        .map_err(|_| DeployError::config(format!("environment variable {} is not set", name)))
+}
+
+// Accepts the key with or without 0x and surrounding whitespace, returns bare lowercase hex.
+fn normalize_hex_key(key: Zeroizing<String>) -> DeployResult<Zeroizing<String>> {
+    let trimmed: &str = key.trim().trim_start_matches("0x");
+    if trimmed.len() != 64 || !trimmed.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(key_error("the key is not 32 bytes of hex".to_string()));
+    }
+    Ok(Zeroizing::new(trimmed.to_ascii_lowercase()))
+}
+
+fn wallet_key(wallet: &LocalWallet) -> Zeroizing<String> {
+    let mut bytes = wallet.signer().to_bytes();
+    let key: Zeroizing<String> = Zeroizing::new(hex::encode(&bytes[..]));
+    bytes[..].zeroize();
+    key
+}
+
+fn keystore_password(path: &str, password_env: Option<&str>) -> DeployResult<Zeroizing<String>> {
+    if let Some(password) = password_env.and_then(|name| std::env::var(name).ok()) {
+        return Ok(Zeroizing::new(password));
+    }
+    rpassword::prompt_password(format!("Password for {}: ", path))
+        .map(Zeroizing::new)
+        .map_err(|e| DeployError::config(format!("failed to read the keystore password: {}", e)))
+}
+
+impl KeySource {
+    // Returns the private key as hex, held in a buffer that is wiped when dropped.
+    pub fn read(&self) -> DeployResult<Zeroizing<String>> {
+        match self {
+            KeySource::File(path) => normalize_hex_key(read_secret_file(path)?),
+            KeySource::Env(name) => normalize_hex_key(read_secret_env(name)?),
+            KeySource::Keystore { path, password_env } => {
+                warn_if_world_readable(path);
+                let password: Zeroizing<String> = keystore_password(path, password_env.as_deref())?;
+                let wallet: LocalWallet =
+                    LocalWallet::decrypt_keystore(path, password.as_bytes())
+                        .map_err(|e| key_error(format!("failed to decrypt keystore {}: {}", path, e)))?;
+                Ok(wallet_key(&wallet))
+            },
+            KeySource::Mnemonic { phrase_env, phrase_file, derivation_path } => {
+                let phrase: Zeroizing<String> =
+                    match (phrase_env, phrase_file) {
+                        (Some(name), None) => read_secret_env(name)?,
+                        (None, Some(path)) => read_secret_file(path)?,
+                        _ => return Err(DeployError::config("a mnemonic key needs exactly one of phrase_env and phrase_file")),
+                    };
+                let wallet: LocalWallet =
+                    MnemonicBuilder::<English>::default()
+                        .phrase(phrase.trim())
+                        .derivation_path(derivation_path)
+                        .and_then(|builder| builder.build())
+                        .map_err(|e| key_error(format!("failed to derive {} from the mnemonic: {}", derivation_path, e)))?;
+                Ok(wallet_key(&wallet))
+            },
+            KeySource::Remote { url, .. } => Err(DeployError::config(format!("the remote signer at {} does not expose its key", url))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use web3::types::Address;
+    use crate::signing::{ LocalSigner, Signer };
+
+    const KEY: &str = "4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318";
+
+    fn normalize(key: &str) -> DeployResult<Zeroizing<String>> {
+        normalize_hex_key(Zeroizing::new(key.to_string()))
+    }
+
+    #[test]
+    fn normalizes_prefixed_and_padded_keys() {
+        assert_eq!(normalize(KEY).unwrap().as_str(), KEY);
+        assert_eq!(normalize(&format!("0x{}", KEY)).unwrap().as_str(), KEY);
+        assert_eq!(normalize(&format!("  0x{}\n", KEY.to_ascii_uppercase())).unwrap().as_str(), KEY);
+    }
+
+    #[test]
+    fn rejects_keys_that_are_not_32_bytes_of_hex() {
+        for key in [&KEY[2..], &format!("{}00", KEY), &format!("{}zz", &KEY[2..]), ""] {
+            let error: DeployError = normalize(key).unwrap_err();
+            assert!(matches!(error.kind, ErrorKind::Signing(_)), "{} was accepted", key);
+        }
+    }
+
+    #[test]
+    fn derives_the_first_account_of_the_test_mnemonic() {
+        let name: &str = "KEYS_TEST_MNEMONIC";
+        std::env::set_var(name, "test test test test test test test test test test test junk\n");
+        let source = KeySource::Mnemonic {
+            phrase_env: Some(name.to_string()),
+            phrase_file: None,
+            derivation_path: "m/44'/60'/0'/0/0".to_string(),
+        };
+        let key: Zeroizing<String> = source.read().unwrap();
+        let address: Address = LocalSigner::from_hex(&key).unwrap().address();
+        assert_eq!(address, "f39Fd6e51aad88F6F4ce6aB8827279cffFb92266".parse().unwrap());
+    }
+}
@@ -1,50 +1,54 @@
 use std::fmt;
 use std::str::FromStr;
-// use std::fs::File;
-// use std::io::Read;
-// use std::time::{ SystemTime, Duration };
 
-// use serde_json::{ to_vec, Value };
+use web3::types::{ Address, BlockId, BlockNumber, Bytes, CallRequest, TransactionParameters, TransactionReceipt, U256 };
+use web3::contract::{ Contract, Options };
+use web3::contract::tokens::{ Detokenize, Tokenize };
 
-use secp256k1::{ SecretKey };
+use neonevm_sdk::types::{ EthAddress, ExpandToDecimals, Erc20Specs, Erc20Deploy, Erc20DeploySpecs };
 
-use web3::types::{ Address, U256 };
-// use web3::contract::{ Contract, Options };
-use web3::contract::{ Options };
-use ethers_tools::EthersUtils;
+use crate::address_book::AddressBook;
+use crate::artifact::{ Artifact, ArtifactRegistry };
+use crate::signers::{ DEPLOYER, RoleSigner, SignerRegistry };
+use crate::config::{ GasSettings, SubmissionSettings };
+use crate::journal::Journal;
+use crate::gas::{ GasPlan, GasStrategy, call_request };
+use crate::nonce::Pipeline;
+use crate::error::{ DeployError, DeployResult, ErrorKind, replay_revert_reason };
 
-// use web3_tools::{ AsEip55 };
+// Seconds Router02 is given to include a liquidity add, by the chain's clock.
+const LIQUIDITY_DEADLINE_SECS: u64 = 1200;
 
-use neonevm_sdk::{
-    // network::Network,
-    types::{ EthAddress, ExpandToDecimals, Erc20Specs, Erc20Deploy, Erc20DeploySpecs },
-};
-
-// use crate::ContractPaths;
-
-
-// pub fn get_contract_from_abi_file(web3: &web3::Web3<web3::transports::Http>, abi_file_path: &str, eth_contract_address: EthAddress) -> Result<Contract<web3::transports::Http>,()> {
-
-//     // open the abi file
-//     let abi = File::open(abi_file_path);
-//     if abi.is_err() {
-//         println!("Failed to open {}\n", abi_file_path);
-//         return Err(());
-//     }
+pub fn apply_slippage(amount: U256, slippage_bps: u64) -> DeployResult<U256> {
+    if slippage_bps > 10_000 {
+        return Err(DeployError::config(format!("slippage of {} basis points is more than 100%", slippage_bps)));
+    }
+    Ok(amount * (10_000 - slippage_bps) / 10_000)
+}
 
-//     // read the abi file
-//     let mut abi_data = String::new();
-//     let bytes_read = abi.unwrap().read_to_string(&mut abi_data);
-//     if bytes_read.is_err() {
-//         println!("Failed to read from {}\n", abi_file_path);
-//         return Err(());
-//     }
+// What the router's _addLiquidity will take from the desired amounts: all of them into
+// an empty pair, otherwise as much as fits the pair's current price. With only one
+// reserve empty UniswapV2Library.quote reverts, so that is an error here as well.
+pub fn quote_deposit(reserve_a: U256, reserve_b: U256, amount_a: U256, amount_b: U256) -> DeployResult<(U256, U256)> {
+    if reserve_a.is_zero() && reserve_b.is_zero() {
+        return Ok((amount_a, amount_b));
+    }
+    if reserve_a.is_zero() || reserve_b.is_zero() {
+        return Err(DeployError::config(format!("the pair's reserves are {} and {}, the router rejects deposits with INSUFFICIENT_LIQUIDITY", reserve_a, reserve_b)).for_contract("uniswap_v2pair"));
+    }
+    let amount_b_optimal: U256 = amount_a * reserve_b / reserve_a;
+    if amount_b_optimal <= amount_b {
+        Ok((amount_a, amount_b_optimal))
+    } else {
+        Ok((amount_b * reserve_a / reserve_b, amount_b))
+    }
+}
 
-//     let lib: Value = serde_json::from_str(&abi_data).unwrap();
-//     let lib_abi: Vec<u8> = to_vec(&lib["abi"]).unwrap();
+// Reserves of a pair in (token_a, token_b) order; the pair stores them sorted by address.
+fn reserves_in_order(token_a: Address, token_b: Address, reserve0: U256, reserve1: U256) -> (U256, U256) {
+    if token_a < token_b { (reserve0, reserve1) } else { (reserve1, reserve0) }
+}
 
-//     Contract::from_json(web3.eth(), eth_contract_address.into(), &lib_abi).map_err(|_|())
-// }
 
 pub enum Erc20Means {
     Origin,
@@ -57,22 +61,16 @@ pub struct Erc20Token {
     pub means: Erc20Means,
 }
 
-impl<'a> Erc20Token {
-    // pub fn get_abi_path(&self, paths: &'a ContractPaths) -> &'a str {
-    //     match self.means {
-    //         Erc20Means::Origin    => &paths.erc20,
-    //         Erc20Means::Bridge(_) => &paths.erc20wrapper,
-    //     }
-    // }
+impl Erc20Token {
     pub fn get_name(&self) -> &str {
         &self.specs.name
     }
     pub fn get_symbol(&self) -> &str {
         &self.specs.symbol
     }
-    // pub fn get_address(&self) -> Address {
-    //     (&self.eth_address).as_ref().clone()
-    // }
+    pub fn get_address(&self) -> Address {
+        *self.eth_address.as_ref()
+    }
 }
 
 impl From<Erc20DeploySpecs> for Erc20Token {
@@ -112,9 +110,9 @@ impl WethToken {
             eth_address: EthAddress::from_str(a).unwrap(),
         }
     }
-    // pub fn get_address(&self) -> Address {
-    //     (&self.eth_address).as_ref().clone()
-    // }
+    pub fn get_address(&self) -> Address {
+        *self.eth_address.as_ref()
+    }
 }
 
 impl fmt::Display for WethToken {
@@ -135,31 +133,25 @@ pub enum SwapToken {
     Erc20(Erc20Token),
 }
 
-impl<'a> SwapToken {
-    // pub fn get_abi_path(&self, paths: &'a ContractPaths) -> &'a str {
-    //     match self {
-    //         SwapToken::Weth(_)      => &paths.weth9,
-    //         SwapToken::Erc20(erc20) => erc20.get_abi_path(paths),
-    //     }
-    // }
-    // pub fn get_name(&self) ->  &str {
-    //     match self {
-    //         SwapToken::Weth(_)      => "Neon Token",
-    //         SwapToken::Erc20(erc20) => erc20.get_name(),
-    //     }
-    // }
-    // pub fn get_symbol(&self) ->  &str {
-    //     match self {
-    //         SwapToken::Weth(_)      => "NEON",
-    //         SwapToken::Erc20(erc20) => erc20.get_symbol(),
-    //     }
-    // }
-    // pub fn get_address(&self) ->  Address {
-    //     match self {
-    //         SwapToken::Weth(t)  => t.get_address(),
-    //         SwapToken::Erc20(t) => t.get_address(),
-    //     }
-    // }
+impl SwapToken {
+    pub fn get_name(&self) ->  &str {
+        match self {
+            SwapToken::Weth(_)      => "Neon Token",
+            SwapToken::Erc20(erc20) => erc20.get_name(),
+        }
+    }
+    pub fn get_symbol(&self) ->  &str {
+        match self {
+            SwapToken::Weth(_)      => "NEON",
+            SwapToken::Erc20(erc20) => erc20.get_symbol(),
+        }
+    }
+    pub fn get_address(&self) ->  Address {
+        match self {
+            SwapToken::Weth(t)  => t.get_address(),
+            SwapToken::Erc20(t) => t.get_address(),
+        }
+    }
     pub fn expand_from_uint(&self, amount: u32) -> u128 {
         match self {
             SwapToken::Weth(_)  => amount.expand_to_decimals(18).unwrap(),
@@ -186,274 +178,475 @@ impl fmt::Debug for SwapToken {
 
 pub struct NeonswapEnvironment {
     pub web3: web3::Web3<web3::transports::Http>,
-    // contract_paths: ContractPaths,
-    pub signing_key: SecretKey,
-    pub signing_address: Address,
-    // eth_weth9_address: EthAddress,
-    // eth_uniswap_v2factory_address: EthAddress,
-    // pub uniswap_v1exchange: Contract<web3::transports::Http>,
-    // uniswap_v1factory: Contract<web3::transports::Http>,
-    // uniswap_v2factory: Contract<web3::transports::Http>,
-    // eth_uniswap_v2router02_address: EthAddress,
-    // uniswap_v2router02: Contract<web3::transports::Http>,
+    pub signers: SignerRegistry,
+    pub address_book: AddressBook,
+    gas: GasSettings,
+    journal: Journal,
+    submission: SubmissionSettings,
+    weth9_address: Address,
+    uniswap_v2factory: Contract<web3::transports::Http>,
+    uniswap_v2router02: Contract<web3::transports::Http>,
+    erc20_artifact: Artifact,
+    pair_artifact: Artifact,
 }
 
 impl NeonswapEnvironment {
-    pub fn default_web3_options(&self) -> Options {
-        Options::with(|o|{
-            o.gas = Some(U256::from(3000000));
-            o.gas_price = Some(U256::from(1000000000));
-        })
+    pub fn gas_strategy(&self) -> GasStrategy<'_> {
+        GasStrategy::new(&self.web3, &self.gas)
+    }
+    // Sends a single transaction from the role's account through its nonce manager and
+    // waits for the receipt. A reverted transaction is an error carrying the revert reason.
+    async fn send(&self, role: &str, operation: &str, request: CallRequest) -> DeployResult<TransactionReceipt> {
+
+        let signer: &RoleSigner = self.signers.get(role)?;
+        let gas_plan: GasPlan = self.gas_strategy().plan(operation, request.clone()).await.map_err(|e| e.at_step(operation))?;
+        let data: Bytes = request.data.clone().unwrap_or_default();
+        let tx = TransactionParameters {
+            value: request.value.unwrap_or_default(),
+            ..gas_plan.transaction(request.to, data.clone())
+        };
+
+        let mut pipeline: Pipeline = Pipeline::new(&self.web3, &*signer.signer, &signer.nonces, &self.journal, &self.submission);
+        pipeline.submit(operation, tx).await?;
+        let receipt: TransactionReceipt =
+            match pipeline.wait().await.pop() {
+                Some((_, result)) => result?,
+                None => return Err(DeployError::rpc("no receipt for a submitted transaction").at_step(operation)),
+            };
+        gas_plan.report(&receipt);
+
+        if receipt.status.map(|s| s.as_u64()).unwrap_or(0u64) == 0 {
+            let reason: Option<String> = replay_revert_reason(&self.web3, signer.address, request.to, data, &receipt).await;
+            return Err(DeployError::reverted(reason).at_step(operation).with_tx(receipt.transaction_hash));
+        }
+        Ok(receipt)
     }
+    pub async fn send_call(&self, role: &str, operation: &str, contract: &Contract<web3::transports::Http>, method: &str, params: impl Tokenize) -> DeployResult<TransactionReceipt> {
+        let from: Address = self.signers.get(role)?.address;
+        self.send(role, operation, call_request(contract, method, params, from)?).await
+    }
+    pub async fn send_payable(&self, role: &str, operation: &str, contract: &Contract<web3::transports::Http>, method: &str, params: impl Tokenize, value: U256) -> DeployResult<TransactionReceipt> {
+        let from: Address = self.signers.get(role)?.address;
+        let request = CallRequest {
+            value: Some(value),
+            ..call_request(contract, method, params, from)?
+        };
+        self.send(role, operation, request).await
+    }
+    pub async fn send_value(&self, role: &str, operation: &str, to: Address, value: U256) -> DeployResult<TransactionReceipt> {
+        let request = CallRequest {
+            from: Some(self.signers.get(role)?.address),
+            to: Some(to),
+            value: Some(value),
+            ..CallRequest::default()
+        };
+        self.send(role, operation, request).await
+    }
+    // Tops every role account up to its configured NEON and test token balances,
+    // paid for by the deployer.
+    pub async fn fund_roles(&self, artifacts: &ArtifactRegistry) -> DeployResult<()> {
+
+        let deployer: Address = self.signers.deployer().address;
+
+        for signer in self.signers.iter().filter(|s| s.role != DEPLOYER) {
+            let neon_target: U256 = U256::from(signer.funding.neon) * U256::exp10(18);
+            let neon_balance: U256 = self.web3.eth().balance(signer.address, None).await.map_err(DeployError::rpc)?;
+            if neon_balance < neon_target {
+                self.send_value(DEPLOYER, "fund_neon", signer.address, neon_target - neon_balance).await?;
+                println!("Funded {} with {} wei NEON", signer.role, neon_target - neon_balance);
+            }
+
+            for (token, amount) in signer.funding.tokens.iter() {
+                let contract: Contract<web3::transports::Http> = artifacts.contract(&self.web3, "erc20", self.contract_address(token)?)?;
+                let query_error = |e: web3::contract::Error| DeployError::rpc(e).for_contract(token);
+                let decimals: u8 = contract.query("decimals", (), deployer, Options::default(), None).await.map_err(query_error)?;
+                let target: U256 = U256::from(*amount) * U256::exp10(decimals as usize);
+                let balance: U256 = contract.query("balanceOf", signer.address, deployer, Options::default(), None).await.map_err(query_error)?;
+                if balance < target {
+                    self.send_call(DEPLOYER, "fund_tokens", &contract, "transfer", (signer.address, target - balance))
+                        .await
+                        .map_err(|e| e.for_contract(token))?;
+                    println!("Funded {} with {} {}", signer.role, target - balance, token);
+                }
+            }
+        }
+        Ok(())
+    }
+    // Contracts compare times against the latest block, not the local clock.
+    pub async fn chain_time(&self) -> DeployResult<U256> {
+        let block = self.web3.eth().block(BlockId::Number(BlockNumber::Latest)).await.map_err(DeployError::rpc)?;
+        block.map(|b| b.timestamp).ok_or_else(|| DeployError::rpc("the node returned no latest block"))
+    }
+    pub fn contract_address(&self, name: &str) -> DeployResult<Address> {
+        self.address_book.address(name)
+            .ok_or_else(|| DeployError::address_book(format!("contract '{}' is not in the address book", name)))
+    }
+    // The V2 handles come from the address book, so the contracts have to be deployed.
     pub fn new(web3: web3::Web3<web3::transports::Http>,
-        // contract_paths: ContractPaths,
-        signing_key_string: &str,
-        signing_key_utils: EthersUtils,
-        // uniswap_v1_exchange_address: Address,
-        // uniswap_v1_factory_address: Address,
-        // uniswap_v2_factory_address: Address,
-        // uniswap_v2_router02_address: Address,
-    ) -> Self {
-
-        // let eth_uniswap_v1exchange_address: EthAddress = EthAddress::from_str(&uniswap_v1_exchange_address.as_eip55()).unwrap();
-        // let eth_uniswap_v1factory_address: EthAddress = EthAddress::from_str(&uniswap_v1_factory_address.as_eip55()).unwrap();
-        // let eth_uniswap_v2factory_address: EthAddress = EthAddress::from_str(&uniswap_v2_factory_address.as_eip55()).unwrap();
-        // let eth_uniswap_v2router02_address: EthAddress = EthAddress::from_str(&uniswap_v2_router02_address.as_eip55()).unwrap();
-
-        // let uniswap_v1exchange = get_contract_from_abi_file(&web3, &contract_paths.uniswap_v1exchange, eth_uniswap_v1exchange_address.clone()).unwrap();
-        // let uniswap_v1factory = get_contract_from_abi_file(&web3, &contract_paths.uniswap_v1factory, eth_uniswap_v1factory_address.clone()).unwrap();
-        // let uniswap_v2factory = get_contract_from_abi_file(&web3, &contract_paths.uniswap_v2factory, eth_uniswap_v2factory_address.clone()).unwrap();
-        // let uniswap_v2router02 = get_contract_from_abi_file(&web3, &contract_paths.uniswap_v2router02, eth_uniswap_v2router02_address.clone()).unwrap();
-        NeonswapEnvironment {
+        signers: SignerRegistry,
+        address_book: AddressBook,
+        gas: GasSettings,
+        journal: Journal,
+        submission: SubmissionSettings,
+        artifacts: &ArtifactRegistry,
+    ) -> DeployResult<Self> {
+
+        let contract_address = |name: &str| {
+            address_book.address(name)
+                .ok_or_else(|| DeployError::address_book(format!("contract '{}' is not in the address book", name)))
+        };
+        let weth9_address: Address = contract_address("weth9")?;
+        let uniswap_v2factory = artifacts.contract(&web3, "uniswap_v2factory", contract_address("uniswap_v2factory")?)?;
+        let uniswap_v2router02 = artifacts.contract(&web3, "uniswap_v2router02", contract_address("uniswap_v2router02")?)?;
+
+        Ok(NeonswapEnvironment {
+            erc20_artifact: artifacts.get("erc20")?.clone(),
+            pair_artifact: artifacts.get("uniswap_v2pair")?.clone(),
             web3,
-            // contract_paths,
-            signing_key: SecretKey::from_str(signing_key_string).unwrap(),
-            signing_address: signing_key_utils.address(),
-            // eth_uniswap_v2factory_address,
-            // uniswap_v1exchange,
-            // uniswap_v1factory,
-            // uniswap_v2factory,
-            // eth_uniswap_v2router02_address,
-            // uniswap_v2router02,
+            signers,
+            address_book,
+            gas,
+            journal,
+            submission,
+            weth9_address,
+            uniswap_v2factory,
+            uniswap_v2router02,
+        })
+    }
+    // A read-only call from the deployer, errors name the contract.
+    pub async fn query<R: Detokenize>(&self, contract: &Contract<web3::transports::Http>, name: &str, method: &str, params: impl Tokenize) -> DeployResult<R> {
+        self.query_at(contract, name, method, params, None).await
+    }
+    pub async fn query_at<R: Detokenize>(&self, contract: &Contract<web3::transports::Http>, name: &str, method: &str, params: impl Tokenize, block: Option<BlockId>) -> DeployResult<R> {
+        contract.query(method, params, self.signers.deployer().address, Options::default(), block)
+            .await
+            .map_err(|e| DeployError::rpc(e).for_contract(name))
+    }
+    pub async fn get_pair(&self, token_a: &SwapToken, token_b: &SwapToken) -> DeployResult<Address> {
+
+        let pair: Address = self.query(&self.uniswap_v2factory, "uniswap_v2factory", "getPair", (token_a.get_address(), token_b.get_address())).await?;
+
+        if !pair.is_zero() {
+            println!("{} [ {} ] <-> {} [ {} ] Pair Address: {:?}", token_a.get_name(), token_a.get_symbol(), token_b.get_name(), token_b.get_symbol(), pair);
+        };
+
+        Ok(pair)
+    }
+    pub async fn pair_exists(&self, token_a: &SwapToken, token_b: &SwapToken) -> DeployResult<bool> {
+        Ok(!self.get_pair(token_a, token_b).await?.is_zero())
+    }
+    pub async fn create_pair(&self, role: &str, token_a: &SwapToken, token_b: &SwapToken) -> DeployResult<Address> {
+
+        self.send_call(role, "create_pair", &self.uniswap_v2factory, "createPair", (token_a.get_address(), token_b.get_address()))
+            .await
+            .map_err(|e| e.for_contract("uniswap_v2factory"))?;
+        println!("{} [ {} ] <-> {} [ {} ] Pair Created!", token_a.get_name(), token_a.get_symbol(), token_b.get_name(), token_b.get_symbol());
+
+        self.get_pair(token_a, token_b).await
+    }
+    // Reserves of the pair in (token_a, token_b) order, zero while it does not exist.
+    pub async fn pair_reserves(&self, token_a: Address, token_b: Address) -> DeployResult<(U256, U256)> {
+
+        let pair: Address = self.query(&self.uniswap_v2factory, "uniswap_v2factory", "getPair", (token_a, token_b)).await?;
+        if pair.is_zero() {
+            return Ok((U256::zero(), U256::zero()));
         }
+
+        let contract: Contract<web3::transports::Http> = self.pair_artifact.at(&self.web3, pair);
+        let (reserve0, reserve1, _): (U256, U256, u32) = self.query(&contract, "uniswap_v2pair", "getReserves", ()).await?;
+        Ok(reserves_in_order(token_a, token_b, reserve0, reserve1))
+    }
+    // Lets Router02 pull `amount` of the token from the role, unless it already may.
+    pub async fn approve_liquidity(&self, role: &str, erc20_token: &Erc20Token, amount: U256) -> DeployResult<()> {
+
+        let contract: Contract<web3::transports::Http> = self.erc20_artifact.at(&self.web3, erc20_token.get_address());
+        let owner: Address = self.signers.get(role)?.address;
+        let router: Address = self.uniswap_v2router02.address();
+
+        let allowance: U256 = self.query(&contract, "erc20", "allowance", (owner, router)).await?;
+        if allowance >= amount {
+            return Ok(());
+        }
+
+        self.send_call(role, "approve_liquidity", &contract, "approve", (router, amount))
+            .await
+            .map_err(|e| e.for_contract("erc20"))?;
+        println!("{} [ {} ] : {}  {:?} -> {:?} Approved!", erc20_token.get_name(), erc20_token.get_symbol(), amount, owner, router);
+        Ok(())
     }
-//     async fn get_pair(&self, token_a: &SwapToken, token_b: &SwapToken) -> EthAddress {
-
-//         let token_a_address: Address = token_a.get_address();
-//         let token_b_address: Address = token_b.get_address();
-
-//         let uniswap_v2_get_pair_ab_address: Address = 
-//             self.uniswap_v2factory.query(
-//                     "getPair",
-//                     (token_a_address,token_b_address),
-//                     self.signing_address,
-//                     self.default_web3_options(),
-//                     None,
-//                 )
-//                 .await
-//                 .unwrap();
-        
-//         if !uniswap_v2_get_pair_ab_address.is_zero() {
-//             println!("{} [ {} ] <-> {} [ {} ] Pair Address: {:?}", token_a.get_name(), token_a.get_symbol(), token_b.get_name(), token_b.get_symbol(), uniswap_v2_get_pair_ab_address);
-//         };
-
-//         uniswap_v2_get_pair_ab_address.into()
-//     }
-//     async fn pair_exists(&self, token_a: &SwapToken, token_b: &SwapToken) -> bool {
-//         self.get_pair(token_a, token_b)
-//             .await
-//             .is_zero()
-//     }
-//     async fn create_pair(&self, token_a: &SwapToken, token_b: &SwapToken) {
-
-//         let token_a_address: Address = token_a.get_address();
-//         let token_b_address: Address = token_b.get_address();
-
-//         let create_pair = 
-//             self.uniswap_v2factory.signed_call_with_confirmations(
-//                     "createPair",
-//                     (token_a_address,token_b_address),
-//                     self.default_web3_options(),
-//                     0,
-//                     &self.signing_key
-//                 )
-//                 .await;
-        
-//         match create_pair {
-//             Ok(receipt) => {
-//                 let status: u64 = receipt.status.map(|s| s.as_u64()).unwrap_or(0u64);
-//                 if status > 0 {
-//                     println!("{} [ {} ] <-> {} [ {} ] Pair Created!", token_a.get_name(), token_a.get_symbol(), token_b.get_name(), token_b.get_symbol());
-//                 } else {
-//                     println!("Create Pair {} [ {} ] <-> {} [ {} ] Declined with Receipt:\n{:?}", token_a.get_name(), token_a.get_symbol(), token_b.get_name(), token_b.get_symbol(), receipt);
-//                 }
-//             },
-//             Err(error) => {
-//                 println!("Create Pair {} [ {} ] <-> {} [ {} ] failed with Error:\n{:?}", token_a.get_name(), token_a.get_symbol(), token_b.get_name(), token_b.get_symbol(), error);
-//             },
-//         }
-
-//     }
-//     async fn approve_liquidity(&self, erc20_token: &Erc20Token, amount: u128) {
-
-//         let contract = get_contract_from_abi_file(&self.web3, erc20_token.get_abi_path(&self.contract_paths), erc20_token.get_address().into()).unwrap();
-
-//         let uniswap_v2router02_address: Address = (&self.eth_uniswap_v2router02_address).as_ref().clone();
-//         let amount_u256: U256 = U256::from(amount);
-
-//         let approve = 
-//             contract.signed_call_with_confirmations(
-//                     "approve",
-//                     (uniswap_v2router02_address, amount_u256),
-//                     self.default_web3_options(),
-//                     0,
-//                     &self.signing_key
-//                 )
-//                 .await;
-
-//         match approve {
-//             Ok(receipt) => {
-//                 let status: u64 = receipt.status.map(|s| s.as_u64()).unwrap_or(0u64);
-//                 if status > 0 {
-//                     println!("{} [ {} ] : {}  {} -> {} Approved!", erc20_token.get_name(), erc20_token.get_symbol(), amount, EthAddress::from(self.signing_address), self.eth_uniswap_v2router02_address);
-//                 } else {
-//                     println!("Approve {} [ {} ] : {}  {} -> {} Declined with Receipt:\n{:?}", erc20_token.get_name(), erc20_token.get_symbol(), amount, EthAddress::from(self.signing_address), self.eth_uniswap_v2router02_address, receipt);
-//                 }
-//             },
-//             Err(error) => {
-//                 println!("Approve {} [ {} ] : {}  {} -> {} failed with Error:\n{:?}", erc20_token.get_name(), erc20_token.get_symbol(), amount, EthAddress::from(self.signing_address), self.eth_uniswap_v2router02_address, error);
-//             },
-//         }
-//     }
-//     async fn add_liquidity(&self, token_a: &Erc20Token, token_b: &Erc20Token, amount_a: u128, amount_b: u128) {
-
-//         let token_a_address: Address = token_a.get_address();
-//         let token_b_address: Address = token_b.get_address();
-
-//         let amount_a_u256: U256 = U256::from(amount_a);
-//         let amount_b_u256: U256 = U256::from(amount_b);
-
-//         let deadline = SystemTime::now()
-//             .checked_add(Duration::from_secs(3600))
-//             .unwrap()
-//             .duration_since(SystemTime::UNIX_EPOCH)
-//             .unwrap()
-//             .as_secs()
-//             * 1000;
-//         let deadline_u256: U256 = U256::from(deadline);
-
-//         let add_liquidity = 
-//             self.uniswap_v2router02.signed_call_with_confirmations(
-//                     "addLiquidity",
-//                     (token_a_address, token_b_address, amount_a_u256, amount_b_u256, amount_a_u256, amount_b_u256, self.signing_address, deadline_u256),
-//                     self.default_web3_options(),
-//                     0,
-//                     &self.signing_key,
-//                 )
-//                 .await;
-
-//         match add_liquidity {
-//             Ok(receipt) => {
-//                 let status: u64 = receipt.status.map(|s| s.as_u64()).unwrap_or(0u64);
-//                 if status > 0 {
-//                     println!("{} [ {} ] : {} <-> {} [ {} ] : {} Liquidity Added", token_a.get_name(), token_a.get_symbol(), amount_a, token_b.get_name(), token_b.get_symbol(), amount_b);
-//                 } else {
-//                     println!("Add Liquidity {} [ {} ] : {} <-> {} [ {} ] : {} Declined with Receipt:\n{:?}", token_a.get_name(), token_a.get_symbol(), amount_a, token_b.get_name(), token_b.get_symbol(), amount_b, receipt);
-//                 }
-//             },
-//             Err(error) => {
-//                 println!("Add Liquidity {} [ {} ] : {} <-> {} [ {} ] : {} failed with Error:\n{:?}", token_a.get_name(), token_a.get_symbol(), amount_a, token_b.get_name(), token_b.get_symbol(), amount_b, error);
-//             },
-//         }
-//     }
-//     async fn add_liquidity_eth(&self, token_b: &Erc20Token, amount_weth: u128, amount_b: u128) {
-
-//         let token_b_address: Address = token_b.get_address();
-
-//         let amount_weth_u256: U256 = U256::from(amount_weth);
-//         let amount_b_u256: U256 = U256::from(amount_b);
-
-//         let deadline = SystemTime::now()
-//             .checked_add(Duration::from_secs(3600))
-//             .unwrap()
-//             .duration_since(SystemTime::UNIX_EPOCH)
-//             .unwrap()
-//             .as_secs()
-//             * 1000;
-//         let deadline_u256: U256 = U256::from(deadline);
-
-//         let mut options = self.default_web3_options();
-//         options.value = Some(amount_weth_u256);
-
-//         let add_liquidity_eth = 
-//             self.uniswap_v2router02.signed_call_with_confirmations(
-//                     "addLiquidityETH",
-//                     (token_b_address, amount_b_u256, amount_b_u256, amount_weth, self.signing_address, deadline_u256),
-//                     options,
-//                     0,
-//                     &self.signing_key,
-//                 )
-//                 .await;
-
-//         match add_liquidity_eth {
-//             Ok(receipt) => {
-//                 let status: u64 = receipt.status.map(|s| s.as_u64()).unwrap_or(0u64);
-//                 if status > 0 {
-//                     println!("NEON [ ETH ] : {} <-> {} [ {} ] : {} Liquidity Added!", amount_weth, token_b.get_name(), token_b.get_symbol(), amount_b);
-//                 } else {
-//                     println!("Add Liquidity NEON [ ETH ] : {} <-> {} [ {} ] : {} Declined with Receipt:\n{:?}", amount_weth, token_b.get_name(), token_b.get_symbol(), amount_b, receipt);
-//                 }
-//             },
-//             Err(error) => {
-//                 println!("Add Liquidity NEON [ ETH ] : {} <-> {} [ {} ] : {} failed with Error:\n{:?}", amount_weth, token_b.get_name(), token_b.get_symbol(), amount_b, error);
-//             },
-//         }
-//     }
-//     pub async fn create_pair_add_liquidity(&self, token_a: &SwapToken, token_b: &SwapToken, amount_a: u128, amount_b: u128) -> Result<(),()> {
-
-//         if self.pair_exists(token_a, token_b).await {
-//             self.create_pair(token_a, token_b)
-//                 .await;
-//             self.get_pair(token_a, token_b)
-//                 .await;
-//         }
-
-//         if let SwapToken::Erc20(token_a_params) = token_a {
-//             self.approve_liquidity(token_a_params, amount_a)
-//                 .await;
-//         };
-//         if let SwapToken::Erc20(token_b_params) = token_b {
-//             self.approve_liquidity(token_b_params, amount_b)
-//                 .await;
-//         };
-
-//         match token_a {
-//             SwapToken::Weth(_) => {
-//                 match token_b {
-//                     SwapToken::Weth(_) => {
-//                         unimplemented!()
-//                     },
-//                     SwapToken::Erc20(token_b_params) => {
-//                         self.add_liquidity_eth(token_b_params, amount_a, amount_b)
-//                             .await;
-//                     }
-//                 }
-//             },
-//             SwapToken::Erc20(token_a_params) => {
-//                 match token_b {
-//                     SwapToken::Weth(_) => {
-//                         self.add_liquidity_eth(token_a_params, amount_b, amount_a)
-//                             .await;
-//                     },
-//                     SwapToken::Erc20(token_b_params) => {
-//                         self.add_liquidity(token_a_params, token_b_params, amount_a, amount_b)
-//                             .await;
-//                     }
-//                 }
-//             },
-//         }
-
-//         Ok(())
-
-//     }
-}
\ No newline at end of file
+    pub async fn add_liquidity(&self, role: &str, token_a: &Erc20Token, token_b: &Erc20Token, amount_a: U256, amount_b: U256, slippage_bps: u64) -> DeployResult<TransactionReceipt> {
+
+        let (reserve_a, reserve_b) = self.pair_reserves(token_a.get_address(), token_b.get_address()).await?;
+        let (deposit_a, deposit_b) = quote_deposit(reserve_a, reserve_b, amount_a, amount_b)?;
+        let amount_a_min: U256 = apply_slippage(deposit_a, slippage_bps)?;
+        let amount_b_min: U256 = apply_slippage(deposit_b, slippage_bps)?;
+        let to: Address = self.signers.get(role)?.address;
+        let deadline: U256 = self.chain_time().await? + U256::from(LIQUIDITY_DEADLINE_SECS);
+
+        let receipt: TransactionReceipt =
+            self.send_call(role, "add_liquidity", &self.uniswap_v2router02, "addLiquidity",
+                    (token_a.get_address(), token_b.get_address(), amount_a, amount_b, amount_a_min, amount_b_min, to, deadline))
+                .await
+                .map_err(|e| e.for_contract("uniswap_v2router02"))?;
+        println!("{} [ {} ] : {} <-> {} [ {} ] : {} Liquidity Added", token_a.get_name(), token_a.get_symbol(), deposit_a, token_b.get_name(), token_b.get_symbol(), deposit_b);
+        Ok(receipt)
+    }
+    // Router02 wraps the sent NEON into WETH9, and refunds what the pair's price does not take.
+    pub async fn add_liquidity_eth(&self, role: &str, token: &Erc20Token, amount_weth: U256, amount_token: U256, slippage_bps: u64) -> DeployResult<TransactionReceipt> {
+
+        let (reserve_token, reserve_weth) = self.pair_reserves(token.get_address(), self.weth9_address).await?;
+        let (deposit_token, deposit_weth) = quote_deposit(reserve_token, reserve_weth, amount_token, amount_weth)?;
+        let amount_token_min: U256 = apply_slippage(deposit_token, slippage_bps)?;
+        let amount_weth_min: U256 = apply_slippage(deposit_weth, slippage_bps)?;
+        let to: Address = self.signers.get(role)?.address;
+        let deadline: U256 = self.chain_time().await? + U256::from(LIQUIDITY_DEADLINE_SECS);
+
+        let receipt: TransactionReceipt =
+            self.send_payable(role, "add_liquidity_eth", &self.uniswap_v2router02, "addLiquidityETH",
+                    (token.get_address(), amount_token, amount_token_min, amount_weth_min, to, deadline), amount_weth)
+                .await
+                .map_err(|e| e.for_contract("uniswap_v2router02"))?;
+        println!("NEON [ ETH ] : {} <-> {} [ {} ] : {} Liquidity Added!", deposit_weth, token.get_name(), token.get_symbol(), deposit_token);
+        Ok(receipt)
+    }
+    // The WETH side of a liquidity add is paid in native NEON, an ERC20 side from the token balance.
+    pub async fn balance(&self, role: &str, token: &SwapToken) -> DeployResult<U256> {
+        let owner: Address = self.signers.get(role)?.address;
+        match token {
+            SwapToken::Weth(_) => self.web3.eth().balance(owner, None).await.map_err(DeployError::rpc),
+            SwapToken::Erc20(erc20_token) => {
+                let contract: Contract<web3::transports::Http> = self.erc20_artifact.at(&self.web3, erc20_token.get_address());
+                self.query(&contract, "erc20", "balanceOf", owner).await
+            },
+        }
+    }
+    pub async fn check_balance(&self, role: &str, token: &SwapToken, required: U256) -> DeployResult<()> {
+        let balance: U256 = self.balance(role, token).await?;
+        if balance < required {
+            return Err(DeployError::new(ErrorKind::InsufficientBalance {
+                balance: format!("{} {} of {}", balance, token.get_symbol(), role),
+                required: format!("{} {}", required, token.get_symbol()),
+            }));
+        }
+        Ok(())
+    }
+    pub async fn create_pair_add_liquidity(&self, role: &str, token_a: &SwapToken, token_b: &SwapToken, amount_a: U256, amount_b: U256, slippage_bps: u64) -> DeployResult<()> {
+
+        if token_a.get_address() == token_b.get_address() {
+            return Err(DeployError::config(format!("cannot pair {} [ {} ] with itself", token_a.get_name(), token_a.get_symbol())));
+        }
+
+        if !self.pair_exists(token_a, token_b).await? {
+            self.create_pair(role, token_a, token_b).await?;
+        }
+
+        if let SwapToken::Erc20(token_a_params) = token_a {
+            self.approve_liquidity(role, token_a_params, amount_a).await?;
+        };
+        if let SwapToken::Erc20(token_b_params) = token_b {
+            self.approve_liquidity(role, token_b_params, amount_b).await?;
+        };
+
+        match (token_a, token_b) {
+            (SwapToken::Weth(_), SwapToken::Weth(_)) => {
+                return Err(DeployError::config("a WETH/WETH pair cannot be created"));
+            },
+            (SwapToken::Weth(_), SwapToken::Erc20(token_b_params)) => {
+                self.add_liquidity_eth(role, token_b_params, amount_a, amount_b, slippage_bps).await?;
+            },
+            (SwapToken::Erc20(token_a_params), SwapToken::Weth(_)) => {
+                self.add_liquidity_eth(role, token_a_params, amount_b, amount_a, slippage_bps).await?;
+            },
+            (SwapToken::Erc20(token_a_params), SwapToken::Erc20(token_b_params)) => {
+                self.add_liquidity(role, token_a_params, token_b_params, amount_a, amount_b, slippage_bps).await?;
+            },
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use serde_json::{ json, Value };
+    use web3::ethabi::Token;
+    use super::*;
+    use crate::address_book::tests::address_book;
+    use crate::artifact::tests::registry;
+    use crate::config::KeySource;
+    use crate::test_node::TestNode;
+
+    const GET_PAIR: &str = "0xe6a43905";
+    const GET_RESERVES: &str = "0x0902f1ac";
+    const BALANCE_OF: &str = "0x70a08231";
+
+    fn abi(functions: Value) -> Value {
+        json!({ "abi": functions, "bytecode": "0x6080", "deployedBytecode": "0x6080" })
+    }
+
+    fn address_type() -> Value {
+        json!({ "name": "", "type": "address" })
+    }
+
+    fn uint_type(bits: u32) -> Value {
+        json!({ "name": "", "type": format!("uint{}", bits) })
+    }
+
+    // A node whose factory reports `pair` for every token pair, holding the given reserves.
+    fn pair_node(pair: Address, reserve0: u64, reserve1: u64) -> TestNode {
+        TestNode::start(move |method, params| {
+            let data: &str = params[0]["data"].as_str().unwrap_or_default();
+            let result: Vec<u8> =
+                match method {
+                    "eth_call" if data.starts_with(GET_PAIR) => web3::ethabi::encode(&[Token::Address(pair)]),
+                    "eth_call" if data.starts_with(GET_RESERVES) => {
+                        web3::ethabi::encode(&[Token::Uint(reserve0.into()), Token::Uint(reserve1.into()), Token::Uint(0.into())])
+                    },
+                    _ => return Err(json!({ "code": -32601, "message": "method not found" })),
+                };
+            Ok(json!(Bytes(result)))
+        })
+    }
+
+    fn environment(node: &TestNode) -> NeonswapEnvironment {
+        let key = KeySource::Remote { url: node.url().to_string(), address: Address::repeat_byte(0xde) };
+        let gas: GasSettings = serde_json::from_value(json!({ "limit": { "mode": "fixed", "gas": 3000000 }, "fee": { "mode": "fixed", "price": 1 } })).unwrap();
+        let artifacts: ArtifactRegistry = registry(vec![
+            ("erc20", abi(json!([
+                { "type": "function", "name": "balanceOf", "inputs": [address_type()], "outputs": [uint_type(256)], "stateMutability": "view" },
+            ]))),
+            ("uniswap_v2factory", abi(json!([
+                { "type": "function", "name": "getPair", "inputs": [address_type(), address_type()], "outputs": [address_type()], "stateMutability": "view" },
+            ]))),
+            ("uniswap_v2router02", abi(json!([]))),
+            ("uniswap_v2pair", abi(json!([
+                { "type": "function", "name": "getReserves", "inputs": [], "outputs": [uint_type(112), uint_type(112), uint_type(32)], "stateMutability": "view" },
+            ]))),
+        ]);
+        let contracts = address_book(&[
+            ("weth9", Address::repeat_byte(0xee)),
+            ("uniswap_v2factory", Address::repeat_byte(0xf0)),
+            ("uniswap_v2router02", Address::repeat_byte(0xf2)),
+        ]);
+        NeonswapEnvironment::new(
+            node.web3(),
+            SignerRegistry::load(&key, &BTreeMap::new()).unwrap(),
+            contracts,
+            gas,
+            Journal::new("unused-journal.jsonl"),
+            SubmissionSettings::default(),
+            &artifacts,
+        ).unwrap()
+    }
+
+    fn token(address: Address) -> SwapToken {
+        SwapToken::Erc20(Erc20Token {
+            specs: Erc20Specs { name: "Token".to_string(), symbol: "TKN".to_string(), decimals: 18 },
+            eth_address: address.into(),
+            means: Erc20Means::Origin,
+        })
+    }
+
+    fn config_error<T: fmt::Debug>(result: DeployResult<T>) -> String {
+        match result.map_err(|e| e.kind) {
+            Err(ErrorKind::Config(message)) => message,
+            other => panic!("expected a configuration error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn deposits_everything_into_an_empty_pair() {
+        let (a, b) = quote_deposit(U256::zero(), U256::zero(), U256::from(1000), U256::from(4000)).unwrap();
+        assert_eq!((a, b), (U256::from(1000), U256::from(4000)));
+    }
+
+    #[test]
+    fn deposits_at_the_pair_price() {
+        // 1:4 pair; 1000 A needs only 4000 of the 5000 B offered
+        let (a, b) = quote_deposit(U256::from(100), U256::from(400), U256::from(1000), U256::from(5000)).unwrap();
+        assert_eq!((a, b), (U256::from(1000), U256::from(4000)));
+        // 1000 A would need 4000 B but only 2000 are offered, so B limits A to 500
+        let (a, b) = quote_deposit(U256::from(100), U256::from(400), U256::from(1000), U256::from(2000)).unwrap();
+        assert_eq!((a, b), (U256::from(500), U256::from(2000)));
+        // the router rounds the optimal amount down
+        let (a, b) = quote_deposit(U256::from(3), U256::from(7), U256::from(10), U256::from(100)).unwrap();
+        assert_eq!((a, b), (U256::from(10), U256::from(23)));
+    }
+
+    #[test]
+    fn rejects_a_pair_with_one_empty_reserve() {
+        let message: String = config_error(quote_deposit(U256::zero(), U256::from(400), U256::from(1000), U256::from(4000)));
+        assert!(message.contains("INSUFFICIENT_LIQUIDITY"), "{}", message);
+        config_error(quote_deposit(U256::from(100), U256::zero(), U256::from(1000), U256::from(4000)));
+    }
+
+    #[test]
+    fn applies_slippage_in_basis_points() {
+        assert_eq!(apply_slippage(U256::from(10_000), 0).unwrap(), U256::from(10_000));
+        assert_eq!(apply_slippage(U256::from(10_000), 50).unwrap(), U256::from(9_950));
+        assert_eq!(apply_slippage(U256::from(999), 50).unwrap(), U256::from(994));
+        assert_eq!(apply_slippage(U256::from(10_000), 10_000).unwrap(), U256::zero());
+        let message: String = config_error(apply_slippage(U256::from(10_000), 10_001));
+        assert!(message.contains("10001 basis points"), "{}", message);
+    }
+
+    #[test]
+    fn orders_reserves_like_the_pair_sorts_its_tokens() {
+        let (low, high) = (Address::repeat_byte(0x01), Address::repeat_byte(0x02));
+        assert_eq!(reserves_in_order(low, high, U256::from(10), U256::from(20)), (U256::from(10), U256::from(20)));
+        assert_eq!(reserves_in_order(high, low, U256::from(10), U256::from(20)), (U256::from(20), U256::from(10)));
+    }
+
+    #[tokio::test]
+    async fn reads_pair_reserves_in_the_requested_order() {
+        let node: TestNode = pair_node(Address::repeat_byte(0xaa), 10, 20);
+        let env: NeonswapEnvironment = environment(&node);
+        let (low, high) = (Address::repeat_byte(0x01), Address::repeat_byte(0x02));
+        assert_eq!(env.pair_reserves(low, high).await.unwrap(), (U256::from(10), U256::from(20)));
+        assert_eq!(env.pair_reserves(high, low).await.unwrap(), (U256::from(20), U256::from(10)));
+        assert_eq!(node.calls("eth_call")[1][0]["to"], json!(Address::repeat_byte(0xaa)));
+
+        let empty: TestNode = pair_node(Address::zero(), 10, 20);
+        assert_eq!(environment(&empty).pair_reserves(low, high).await.unwrap(), (U256::zero(), U256::zero()));
+        assert_eq!(empty.calls("eth_call").len(), 1);
+    }
+
+    #[tokio::test]
+    async fn a_pair_exists_once_the_factory_knows_its_address() {
+        let (a, b) = (token(Address::repeat_byte(0x01)), token(Address::repeat_byte(0x02)));
+
+        let node: TestNode = pair_node(Address::repeat_byte(0xaa), 0, 0);
+        assert!(environment(&node).pair_exists(&a, &b).await.unwrap());
+        assert_eq!(node.calls("eth_call")[0][0]["to"], json!(Address::repeat_byte(0xf0)));
+
+        let node: TestNode = pair_node(Address::zero(), 0, 0);
+        assert!(!environment(&node).pair_exists(&a, &b).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn checks_both_sides_of_a_liquidity_add_before_sending() {
+        let node: TestNode = TestNode::start(|method, params| match method {
+            "eth_getBalance" => Ok(json!("0x3e8")),
+            "eth_call" if params[0]["data"].as_str().unwrap_or_default().starts_with(BALANCE_OF) =>
+                Ok(json!(Bytes(web3::ethabi::encode(&[Token::Uint(U256::from(4000))])))),
+            _ => Err(json!({ "code": -32601, "message": "method not found" })),
+        });
+        let env: NeonswapEnvironment = environment(&node);
+        let neon: SwapToken = SwapToken::Weth(WethToken { eth_address: Address::repeat_byte(0xee).into() });
+        let tokens: SwapToken = token(Address::repeat_byte(0x01));
+
+        env.check_balance(DEPLOYER, &neon, U256::from(1000)).await.unwrap();
+        env.check_balance(DEPLOYER, &tokens, U256::from(4000)).await.unwrap();
+        assert_eq!(node.calls("eth_getBalance")[0][0], json!(Address::repeat_byte(0xde)));
+
+        match env.check_balance(DEPLOYER, &tokens, U256::from(4001)).await.map_err(|e| e.kind) {
+            Err(ErrorKind::InsufficientBalance { balance, required }) => {
+                assert_eq!(balance, "4000 TKN of deployer");
+                assert_eq!(required, "4001 TKN");
+            },
+            other => panic!("expected an insufficient balance, got {:?}", other),
+        }
+        assert!(env.check_balance(DEPLOYER, &neon, U256::from(1001)).await.is_err());
+    }
+}
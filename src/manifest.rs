@@ -0,0 +1,388 @@
+use std::collections::HashMap;
+use std::time::{ SystemTime, Duration };
+use serde::{ Deserialize };
+
+use web3::types::{ Address, U256 };
+use web3::ethabi::Token;
+
+use crate::address_book::AddressBook;
+use crate::artifact::ArtifactRegistry;
+use crate::config::GovernanceParams;
+use crate::error::{ DeployError, DeployResult };
+
+#[derive(Clone, Debug)]
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ManifestArg {
+    Deployer,
+    Address(Address),
+    Ref(String),
+    Predicted(String),
+    Uint(String),
+    Ether(u64),
+    SecondsFromNow(u64),
+    // a value from the profile's governance parameters, see Manifest::apply_parameters
+    Param(String),
+}
+
+impl ManifestArg {
+    pub fn reference(&self) -> Option<&str> {
+        match self {
+            ManifestArg::Ref(name) => Some(name),
+            _ => None,
+        }
+    }
+    pub fn referenced_name(&self) -> Option<&str> {
+        match self {
+            ManifestArg::Ref(name)       => Some(name),
+            ManifestArg::Predicted(name) => Some(name),
+            _ => None,
+        }
+    }
+    pub fn to_token(&self, deployer: Address, addresses: &HashMap<String,Address>) -> DeployResult<Token> {
+        match self {
+            ManifestArg::Deployer => Ok(Token::Address(deployer)),
+            ManifestArg::Address(address) => Ok(Token::Address(*address)),
+            ManifestArg::Ref(name) | ManifestArg::Predicted(name) => {
+                addresses.get(name)
+                    .map(|address| Token::Address(*address))
+                    .ok_or_else(|| DeployError::manifest(format!("reference to unknown contract '{}'", name)))
+            },
+            ManifestArg::Uint(value) => {
+                U256::from_dec_str(value)
+                    .map(Token::Uint)
+                    .map_err(|_| DeployError::manifest(format!("value '{}' is not a decimal integer", value)))
+            },
+            ManifestArg::Ether(amount) => {
+                Ok(Token::Uint(U256::from(*amount) * U256::exp10(18)))
+            },
+            ManifestArg::SecondsFromNow(seconds) => {
+                let timestamp = SystemTime::now()
+                    .checked_add(Duration::from_secs(*seconds))
+                    .unwrap()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs();
+                Ok(Token::Uint(U256::from(timestamp)))
+            },
+            ManifestArg::Param(name) => Err(DeployError::manifest(format!("parameter '{}' was not filled in from the profile", name))),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+#[derive(Deserialize)]
+pub struct CallCheck {
+    pub method: String,
+    pub expect: ManifestArg,
+}
+
+#[derive(Clone, Debug)]
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StepAction {
+    Deploy {
+        artifact: String,
+        #[serde(default)]
+        args: Vec<ManifestArg>,
+    },
+    Call {
+        target: String,
+        method: String,
+        #[serde(default)]
+        args: Vec<ManifestArg>,
+        #[serde(default)]
+        check: Option<CallCheck>,
+    },
+}
+
+#[derive(Clone, Debug)]
+#[derive(Deserialize)]
+pub struct ManifestStep {
+    pub name: String,
+    pub title: String,
+    #[serde(flatten)]
+    pub action: StepAction,
+}
+
+impl ManifestStep {
+    pub fn args(&self) -> &[ManifestArg] {
+        match &self.action {
+            StepAction::Deploy { args, .. } => args,
+            StepAction::Call { args, .. }   => args,
+        }
+    }
+    pub fn dependencies(&self) -> Vec<&str> {
+        let mut dependencies: Vec<&str> =
+            self.args()
+                .iter()
+                .filter_map(|arg| arg.reference())
+                .collect();
+        if let StepAction::Call { target, check, .. } = &self.action {
+            dependencies.push(target);
+            if let Some(name) = check.as_ref().and_then(|c| c.expect.reference()) {
+                dependencies.push(name);
+            }
+        }
+        dependencies
+    }
+    pub fn is_deploy(&self) -> bool {
+        matches!(self.action, StepAction::Deploy { .. })
+    }
+}
+
+#[derive(Clone, Debug)]
+#[derive(Deserialize)]
+pub struct Manifest {
+    pub steps: Vec<ManifestStep>,
+}
+
+pub fn read_manifest(path: &str) -> DeployResult<Manifest> {
+
+    let file = std::fs::File::open(path).map_err(|e| DeployError::manifest(format!("manifest file {} not found: {}", path, e)))?;
+
+    let reader = std::io::BufReader::new(file);
+    serde_json::from_reader(reader).map_err(|e| DeployError::manifest(format!("manifest file {} is malformed: {}", path, e)))
+}
+
+impl Manifest {
+    // Replaces every `param` argument with the profile's value for it. A fixed
+    // minting_allowed_after has to be in the future only for steps still to be deployed.
+    pub fn apply_parameters(&mut self, params: &GovernanceParams, address_book: &AddressBook) -> DeployResult<()> {
+
+        params.validate()?;
+        let now: u64 = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        for step in self.steps.iter_mut() {
+            let pending: bool = address_book.address(&step.name).is_none();
+            let args: &mut Vec<ManifestArg> =
+                match &mut step.action {
+                    StepAction::Deploy { args, .. } => args,
+                    StepAction::Call { args, .. }   => args,
+                };
+            for arg in args.iter_mut() {
+                if let ManifestArg::Param(name) = arg {
+                    *arg =
+                        match name.as_str() {
+                            "minting_allowed_after" => {
+                                if pending {
+                                    params.check_minting_allowed_after(now).map_err(|e| e.at_step(&step.name))?;
+                                }
+                                match params.minting_allowed_after {
+                                    Some(timestamp) => ManifestArg::Uint(timestamp.to_string()),
+                                    None => ManifestArg::SecondsFromNow(params.minting_delay_secs),
+                                }
+                            },
+                            "timelock_delay" => ManifestArg::Uint(params.timelock_delay_secs.to_string()),
+                            "initial_recipient" => params.initial_recipient.map(ManifestArg::Address).unwrap_or(ManifestArg::Deployer),
+                            _ => return Err(DeployError::manifest(format!("unknown parameter '{}'", name)).at_step(&step.name)),
+                        };
+                }
+            }
+        }
+        Ok(())
+    }
+    pub fn step(&self, name: &str) -> Option<&ManifestStep> {
+        self.steps.iter().find(|s| s.name == name)
+    }
+    // Orders steps so that every `ref` and call target is handled before the step using it.
+    // `predicted` arguments do not constrain the order, which is how the governance
+    // contracts refer to each other. Ties keep the manifest order.
+    pub fn deployment_order(&self) -> DeployResult<Vec<&ManifestStep>> {
+
+        for (i, step) in self.steps.iter().enumerate() {
+            if self.steps[..i].iter().any(|s| s.name == step.name) {
+                return Err(DeployError::manifest("step is declared twice").at_step(&step.name));
+            }
+            for name in step.args().iter().filter_map(|arg| arg.referenced_name()).chain(step.dependencies()) {
+                match self.step(name) {
+                    Some(s) if s.is_deploy() => {},
+                    Some(_) => return Err(DeployError::manifest(format!("'{}' is not a deployment", name)).at_step(&step.name)),
+                    None    => return Err(DeployError::manifest(format!("reference to unknown contract '{}'", name)).at_step(&step.name)),
+                }
+            }
+        }
+
+        let mut ordered: Vec<&ManifestStep> = Vec::with_capacity(self.steps.len());
+        while ordered.len() < self.steps.len() {
+            let next =
+                self.steps
+                    .iter()
+                    .filter(|s| !ordered.iter().any(|o| o.name == s.name))
+                    .find(|s| s.dependencies().iter().all(|d| ordered.iter().any(|o| o.name == *d)));
+            match next {
+                Some(step) => ordered.push(step),
+                None => {
+                    let pending: Vec<&str> =
+                        self.steps
+                            .iter()
+                            .filter(|s| !ordered.iter().any(|o| o.name == s.name))
+                            .map(|s| s.name.as_str())
+                            .collect();
+                    return Err(DeployError::manifest(format!("reference cycle between: {}", pending.join(", "))));
+                },
+            }
+        }
+
+        Ok(ordered)
+    }
+    // The deployment order with a step for every library an artifact links against,
+    // right before the first step that needs it.
+    pub fn with_libraries(&self, artifacts: &ArtifactRegistry) -> DeployResult<Vec<ManifestStep>> {
+
+        let mut ordered: Vec<ManifestStep> = Vec::new();
+        for step in self.deployment_order()? {
+            if let StepAction::Deploy { artifact, .. } = &step.action {
+                for library in artifacts.get(artifact).map_err(|e| e.at_step(&step.name))?.library_names() {
+                    if self.step(library).is_some() {
+                        continue;
+                    }
+                    if !ordered.iter().any(|s| s.name == library) {
+                        if !artifacts.get(library)?.library_names().is_empty() {
+                            return Err(DeployError::artifact("libraries linking against other libraries are not supported").for_contract(library));
+                        }
+                        ordered.push(
+                            ManifestStep {
+                                name: library.to_string(),
+                                title: format!("Library {}", library),
+                                action: StepAction::Deploy { artifact: library.to_string(), args: Vec::new() },
+                            }
+                        );
+                    }
+                }
+            }
+            ordered.push(step.clone());
+        }
+        Ok(ordered)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+    use crate::address_book::tests::address_book;
+    use crate::error::ErrorKind;
+    use crate::artifact::tests::{ linking_artifact, registry };
+
+    fn from_steps(steps: serde_json::Value) -> Manifest {
+        serde_json::from_value(json!({ "steps": steps })).unwrap()
+    }
+
+    fn order_names(manifest: &Manifest) -> DeployResult<Vec<String>> {
+        manifest.deployment_order().map(|steps| steps.iter().map(|s| s.name.clone()).collect())
+    }
+
+    fn manifest_error(result: DeployResult<Vec<String>>) -> String {
+        match result.map_err(|e| e.kind) {
+            Err(ErrorKind::Manifest(message)) => message,
+            other => panic!("expected a manifest error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn orders_references_before_their_users() {
+        let manifest = from_steps(json!([
+            { "name": "router", "title": "Router", "deploy": { "artifact": "router", "args": [ { "ref": "factory" }, { "ref": "weth" } ] } },
+            { "name": "factory", "title": "Factory", "deploy": { "artifact": "factory", "args": [ "deployer" ] } },
+            { "name": "weth", "title": "WETH", "deploy": { "artifact": "weth" } },
+            { "name": "init", "title": "Init", "call": { "target": "factory", "method": "init", "args": [ { "ref": "router" } ] } },
+        ]));
+        assert_eq!(order_names(&manifest).unwrap(), vec!["factory", "weth", "router", "init"]);
+    }
+
+    #[test]
+    fn predicted_references_do_not_constrain_the_order() {
+        let manifest = from_steps(json!([
+            { "name": "token", "title": "Token", "deploy": { "artifact": "token", "args": [ { "predicted": "timelock" } ] } },
+            { "name": "timelock", "title": "Timelock", "deploy": { "artifact": "timelock", "args": [ { "ref": "token" } ] } },
+        ]));
+        assert_eq!(order_names(&manifest).unwrap(), vec!["token", "timelock"]);
+    }
+
+    #[test]
+    fn rejects_a_reference_cycle() {
+        let manifest = from_steps(json!([
+            { "name": "weth", "title": "WETH", "deploy": { "artifact": "weth" } },
+            { "name": "a", "title": "A", "deploy": { "artifact": "a", "args": [ { "ref": "b" } ] } },
+            { "name": "b", "title": "B", "deploy": { "artifact": "b", "args": [ { "ref": "a" } ] } },
+        ]));
+        assert_eq!(manifest_error(order_names(&manifest)), "reference cycle between: a, b");
+    }
+
+    #[test]
+    fn rejects_a_missing_reference() {
+        let manifest = from_steps(json!([
+            { "name": "router", "title": "Router", "deploy": { "artifact": "router", "args": [ { "ref": "factory" } ] } },
+        ]));
+        assert_eq!(manifest_error(order_names(&manifest)), "reference to unknown contract 'factory'");
+
+        let manifest = from_steps(json!([
+            { "name": "factory", "title": "Factory", "deploy": { "artifact": "factory" } },
+            { "name": "init", "title": "Init", "call": { "target": "factory", "method": "init", "args": [ { "predicted": "router" } ] } },
+        ]));
+        assert_eq!(manifest_error(order_names(&manifest)), "reference to unknown contract 'router'");
+    }
+
+    #[test]
+    fn inserts_libraries_before_their_first_user() {
+        let manifest = from_steps(json!([
+            { "name": "weth", "title": "WETH", "deploy": { "artifact": "weth" } },
+            { "name": "router", "title": "Router", "deploy": { "artifact": "router", "args": [ { "ref": "weth" } ] } },
+            { "name": "migrator", "title": "Migrator", "deploy": { "artifact": "migrator" } },
+        ]));
+        let plain = json!({ "abi": [], "bytecode": "0x6080", "deployedBytecode": "0x6080" });
+        let artifacts = registry(vec![
+            ("weth", plain.clone()),
+            ("router", linking_artifact("Math")),
+            ("migrator", linking_artifact("Math")),
+            ("Math", plain),
+        ]);
+        let names: Vec<String> = manifest.with_libraries(&artifacts).unwrap().into_iter().map(|s| s.name).collect();
+        assert_eq!(names, vec!["weth", "Math", "router", "migrator"]);
+    }
+
+    #[test]
+    fn rejects_a_call_used_as_a_contract() {
+        let manifest = from_steps(json!([
+            { "name": "factory", "title": "Factory", "deploy": { "artifact": "factory" } },
+            { "name": "init", "title": "Init", "call": { "target": "factory", "method": "init" } },
+            { "name": "router", "title": "Router", "deploy": { "artifact": "router", "args": [ { "ref": "init" } ] } },
+        ]));
+        assert_eq!(manifest_error(order_names(&manifest)), "'init' is not a deployment");
+    }
+
+    fn uni_manifest() -> Manifest {
+        from_steps(json!([
+            { "name": "uni", "title": "Uni", "deploy": { "artifact": "uni", "args": [ { "param": "initial_recipient" }, { "param": "minting_allowed_after" } ] } },
+        ]))
+    }
+
+    fn config_error(result: DeployResult<()>) -> String {
+        match result.map_err(|e| e.kind) {
+            Err(ErrorKind::Config(message)) => message,
+            other => panic!("expected a configuration error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rejects_a_past_minting_time_while_uni_is_pending() {
+        let params = GovernanceParams { minting_allowed_after: Some(1_600_000_000), ..GovernanceParams::default() };
+        let message: String = config_error(uni_manifest().apply_parameters(&params, &AddressBook::default()));
+        assert!(message.contains("is in the past"), "{}", message);
+    }
+
+    #[test]
+    fn accepts_a_past_minting_time_once_uni_is_deployed() {
+        let params = GovernanceParams { minting_allowed_after: Some(1_600_000_000), ..GovernanceParams::default() };
+        let address_book: AddressBook = address_book(&[("uni", Address::repeat_byte(0x01))]);
+        let mut manifest: Manifest = uni_manifest();
+        manifest.apply_parameters(&params, &address_book).unwrap();
+        assert!(matches!(&manifest.steps[0].args()[1], ManifestArg::Uint(value) if value == "1600000000"));
+        assert!(matches!(manifest.steps[0].args()[0], ManifestArg::Deployer));
+
+        // unit mistakes are reported either way
+        let params = GovernanceParams { minting_allowed_after: Some(1_600_000_000_000), ..GovernanceParams::default() };
+        let message: String = config_error(uni_manifest().apply_parameters(&params, &address_book));
+        assert!(message.contains("looks like milliseconds"), "{}", message);
+    }
+}
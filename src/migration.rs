@@ -0,0 +1,159 @@
+use web3::types::{ Address, TransactionId, TransactionReceipt, U256 };
+use web3::contract::Contract;
+
+use web3_tools::AsEip55;
+
+use crate::artifact::ArtifactRegistry;
+use crate::liquidity::{ NeonswapEnvironment, apply_slippage, quote_deposit };
+use crate::error::{ DeployError, DeployResult };
+
+// Token and WETH reserves of the V2 pair in (token, WETH) order, zero before it exists.
+#[derive(Clone, Copy, Debug, Default)]
+struct PairState {
+    pair: Address,
+    reserve_token: U256,
+    reserve_eth: U256,
+    balance: U256,
+}
+
+pub struct MigrationReport {
+    pub v1_liquidity: U256,
+    pub v1_remaining: U256,
+    pub removed_token: U256,
+    pub removed_eth: U256,
+    pub min_token: U256,
+    pub min_eth: U256,
+    pub pair: Address,
+    pub v2_liquidity: U256,
+    pub added_token: U256,
+    pub added_eth: U256,
+    // what the migrator sent back to the role, measured from its balances around the migrate call
+    pub refunded_token: U256,
+    pub refunded_eth: U256,
+    pub gas_cost: U256,
+}
+
+impl MigrationReport {
+    pub fn report(&self) {
+        println!("V1 liquidity migrated: {}, left in V1: {}", self.v1_liquidity, self.v1_remaining);
+        println!("    removed {} tokens and {} wei ETH from V1", self.removed_token, self.removed_eth);
+        println!("    minimums {} tokens and {} wei ETH", self.min_token, self.min_eth);
+        println!("V2 liquidity received: {} in pair {}", self.v2_liquidity, self.pair.as_eip55());
+        println!("    added {} tokens and {} wei ETH to V2", self.added_token, self.added_eth);
+        println!("    refunded {} tokens and {} wei ETH, the migrate call cost {} wei gas", self.refunded_token, self.refunded_eth, self.gas_cost);
+    }
+}
+
+// Moves a role's whole V1 position in `token` to the V2 WETH pair through UniswapV2Migrator.
+pub struct Migration<'a> {
+    env: &'a NeonswapEnvironment,
+    artifacts: &'a ArtifactRegistry,
+    token: Address,
+    weth: Address,
+}
+
+impl<'a> Migration<'a> {
+    pub fn new(env: &'a NeonswapEnvironment, artifacts: &'a ArtifactRegistry, token: Address) -> DeployResult<Migration<'a>> {
+        Ok(Migration { env, artifacts, token, weth: env.contract_address("weth9")? })
+    }
+    fn contract(&self, name: &str, address: Address) -> DeployResult<Contract<web3::transports::Http>> {
+        self.artifacts.contract(&self.env.web3, name, address)
+    }
+    async fn v1_exchange(&self) -> DeployResult<Contract<web3::transports::Http>> {
+        let factory = self.contract("uniswap_v1factory", self.env.contract_address("uniswap_v1factory")?)?;
+        let exchange: Address = self.env.query(&factory, "uniswap_v1factory", "getExchange", self.token).await?;
+        if exchange.is_zero() {
+            return Err(DeployError::config(format!("there is no V1 exchange for {}", self.token.as_eip55())));
+        }
+        self.contract("uniswap_v1exchange", exchange)
+    }
+    async fn pair_state(&self, owner: Address) -> DeployResult<PairState> {
+        let factory = self.contract("uniswap_v2factory", self.env.contract_address("uniswap_v2factory")?)?;
+        let pair: Address = self.env.query(&factory, "uniswap_v2factory", "getPair", (self.token, self.weth)).await?;
+        if pair.is_zero() {
+            return Ok(PairState::default());
+        }
+        let contract = self.contract("uniswap_v2pair", pair)?;
+        let (reserve0, reserve1, _): (U256, U256, u32) = self.env.query(&contract, "uniswap_v2pair", "getReserves", ()).await?;
+        let (reserve_token, reserve_eth) = if self.token < self.weth { (reserve0, reserve1) } else { (reserve1, reserve0) };
+        Ok(PairState {
+            pair,
+            reserve_token,
+            reserve_eth,
+            balance: self.env.query(&contract, "uniswap_v2pair", "balanceOf", owner).await?,
+        })
+    }
+    async fn balances(&self, token: &Contract<web3::transports::Http>, owner: Address) -> DeployResult<(U256, U256)> {
+        let eth: U256 = self.env.web3.eth().balance(owner, None).await.map_err(DeployError::rpc)?;
+        Ok((self.env.query(token, "erc20", "balanceOf", owner).await?, eth))
+    }
+    // Nodes without effectiveGasPrice in their receipts charge the transaction's gas price.
+    async fn gas_cost(&self, receipt: &TransactionReceipt) -> DeployResult<U256> {
+        let gas_price: Option<U256> =
+            match receipt.effective_gas_price {
+                Some(price) => Some(price),
+                None => {
+                    self.env.web3.eth().transaction(TransactionId::Hash(receipt.transaction_hash))
+                        .await
+                        .map_err(DeployError::rpc)?
+                        .and_then(|tx| tx.gas_price)
+                },
+            };
+        Ok(receipt.gas_used.unwrap_or_default() * gas_price.unwrap_or_default())
+    }
+    pub async fn migrate(&self, role: &str, slippage_bps: u64, deadline_secs: u64) -> DeployResult<MigrationReport> {
+
+        let owner: Address = self.env.signers.get(role)?.address;
+        let exchange = self.v1_exchange().await?;
+        let token = self.contract("erc20", self.token)?;
+        let migrator = self.contract("uniswap_v2migrator", self.env.contract_address("uniswap_v2migrator")?)?;
+
+        let v1_liquidity: U256 = self.env.query(&exchange, "uniswap_v1exchange", "balanceOf", owner).await?;
+        if v1_liquidity.is_zero() {
+            return Err(DeployError::config(format!("{} holds no V1 liquidity for {}", role, self.token.as_eip55())));
+        }
+
+        // V1 pays out the liquidity's share of the exchange's ETH and token balances.
+        let total_supply: U256 = self.env.query(&exchange, "uniswap_v1exchange", "totalSupply", ()).await?;
+        let exchange_eth: U256 = self.env.web3.eth().balance(exchange.address(), None).await.map_err(DeployError::rpc)?;
+        let exchange_token: U256 = self.env.query(&token, "erc20", "balanceOf", exchange.address()).await?;
+        let removed_eth: U256 = v1_liquidity * exchange_eth / total_supply;
+        let removed_token: U256 = v1_liquidity * exchange_token / total_supply;
+
+        let before: PairState = self.pair_state(owner).await?;
+        let (deposit_token, deposit_eth) = quote_deposit(before.reserve_token, before.reserve_eth, removed_token, removed_eth)?;
+        let min_token: U256 = apply_slippage(deposit_token, slippage_bps)?;
+        let min_eth: U256 = apply_slippage(deposit_eth, slippage_bps)?;
+
+        println!("Migrating {} V1 liquidity of {} ({} tokens, {} wei ETH)", v1_liquidity, role, removed_token, removed_eth);
+        self.env.send_call(role, "migrate_approve", &exchange, "approve", (migrator.address(), v1_liquidity))
+            .await
+            .map_err(|e| e.for_contract("uniswap_v1exchange"))?;
+
+        let deadline: U256 = self.env.chain_time().await? + U256::from(deadline_secs);
+        let (token_before, eth_before) = self.balances(&token, owner).await?;
+        let receipt: TransactionReceipt =
+            self.env.send_call(role, "migrate", &migrator, "migrate", (self.token, min_token, min_eth, owner, deadline))
+                .await
+                .map_err(|e| e.for_contract("uniswap_v2migrator"))?;
+        let (token_after, eth_after) = self.balances(&token, owner).await?;
+        let gas_cost: U256 = self.gas_cost(&receipt).await?;
+
+        let after: PairState = self.pair_state(owner).await?;
+        Ok(MigrationReport {
+            v1_liquidity,
+            v1_remaining: self.env.query(&exchange, "uniswap_v1exchange", "balanceOf", owner).await?,
+            removed_token,
+            removed_eth,
+            min_token,
+            min_eth,
+            pair: after.pair,
+            v2_liquidity: after.balance.saturating_sub(before.balance),
+            added_token: after.reserve_token.saturating_sub(before.reserve_token),
+            added_eth: after.reserve_eth.saturating_sub(before.reserve_eth),
+            refunded_token: token_after.saturating_sub(token_before),
+            refunded_eth: (eth_after + gas_cost).saturating_sub(eth_before),
+            gas_cost,
+        })
+    }
+}
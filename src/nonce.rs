@@ -0,0 +1,425 @@
+use std::cell::Cell;
+use std::time::{ Duration, Instant };
+
+use web3::types::{ Address, BlockNumber, Bytes, TransactionId, TransactionParameters, TransactionReceipt, H256, U256 };
+
+use crate::config::SubmissionSettings;
+use crate::error::{ DeployError, DeployResult, ErrorKind };
+use crate::journal::{ Journal, JournalEvent };
+use crate::signing::{ Signer, SignedTx };
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+// Polls between checks whether pending transactions are still known to the node.
+const GAP_CHECK_POLLS: u32 = 10;
+
+// Hands out nonces locally so several transactions can be in flight at once.
+// Starts from the pending transaction count and counts up from there.
+pub struct NonceManager {
+    address: Address,
+    next: Cell<Option<U256>>,
+}
+
+impl NonceManager {
+    pub fn new(address: Address) -> Self {
+        NonceManager { address, next: Cell::new(None) }
+    }
+    pub async fn next(&self, web3: &web3::Web3<web3::transports::Http>) -> DeployResult<U256> {
+        let nonce: U256 =
+            match self.next.get() {
+                Some(nonce) => nonce,
+                None => web3.eth().transaction_count(self.address, Some(BlockNumber::Pending)).await.map_err(DeployError::rpc)?,
+            };
+        self.next.set(Some(nonce + 1));
+        Ok(nonce)
+    }
+    // The nonce `next` hands out next, without taking it.
+    pub async fn peek(&self, web3: &web3::Web3<web3::transports::Http>) -> DeployResult<U256> {
+        let nonce: U256 = self.next(web3).await?;
+        self.next.set(Some(nonce));
+        Ok(nonce)
+    }
+    // Forgets the local count, the next nonce is read from the node again.
+    pub fn reset(&self) {
+        self.next.set(None);
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PendingTransaction {
+    pub label: String,
+    pub nonce: U256,
+    pub tx_hash: H256,
+    // every attempt sent for this nonce, any of them may be the one that gets mined
+    hashes: Vec<H256>,
+    tx: TransactionParameters,
+    raw: Bytes,
+    sent_at: Instant,
+    replacements: u32,
+    filler: Option<H256>,
+}
+
+// Raises every fee of the transaction by `percent`, and by at least one wei.
+fn bump_fees(tx: &TransactionParameters, percent: u64) -> TransactionParameters {
+    let bump = |fee: Option<U256>| fee.map(|fee| std::cmp::max(fee * (100 + percent) / 100, fee + 1));
+    TransactionParameters {
+        gas_price: bump(tx.gas_price),
+        max_fee_per_gas: bump(tx.max_fee_per_gas),
+        max_priority_fee_per_gas: bump(tx.max_priority_fee_per_gas),
+        ..tx.clone()
+    }
+}
+
+pub struct Pipeline<'a> {
+    web3: &'a web3::Web3<web3::transports::Http>,
+    signer: &'a dyn Signer,
+    nonces: &'a NonceManager,
+    journal: &'a Journal,
+    settings: &'a SubmissionSettings,
+    pending: Vec<PendingTransaction>,
+}
+
+impl<'a> Pipeline<'a> {
+    pub fn new(web3: &'a web3::Web3<web3::transports::Http>, signer: &'a dyn Signer, nonces: &'a NonceManager, journal: &'a Journal, settings: &'a SubmissionSettings) -> Self {
+        Pipeline { web3, signer, nonces, journal, settings, pending: Vec::new() }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+    // Signs with the next local nonce and broadcasts without waiting for the receipt.
+    pub async fn submit(&mut self, label: &str, mut tx: TransactionParameters) -> DeployResult<H256> {
+
+        let nonce: U256 = self.nonces.next(self.web3).await?;
+        tx.nonce = Some(nonce);
+
+        let signed: SignedTx =
+            match self.signer.sign_transaction(self.web3, tx.clone()).await {
+                Ok(signed) => signed,
+                Err(error) => {
+                    self.nonces.reset();
+                    return Err(error.at_step(label));
+                },
+            };
+        if let Err(error) = self.web3.eth().send_raw_transaction(signed.raw_transaction.clone()).await {
+            self.nonces.reset();
+            self.journal.record(label, nonce, Some(signed.transaction_hash), JournalEvent::Failed { error: format!("{:?}", error) });
+            return Err(DeployError::rpc(error).at_step(label).with_tx(signed.transaction_hash));
+        }
+        self.journal.record(label, nonce, Some(signed.transaction_hash), JournalEvent::submitted(0, None, &tx));
+
+        self.pending.push(
+            PendingTransaction {
+                label: label.to_string(),
+                nonce,
+                tx_hash: signed.transaction_hash,
+                hashes: vec![signed.transaction_hash],
+                tx,
+                raw: signed.raw_transaction,
+                sent_at: Instant::now(),
+                replacements: 0,
+                filler: None,
+            }
+        );
+        Ok(signed.transaction_hash)
+    }
+    // Re-sends the same nonce with higher fees once the operation's timeout has passed.
+    async fn replace(&self, pending: &mut PendingTransaction) -> DeployResult<()> {
+
+        let tx: TransactionParameters = bump_fees(&pending.tx, self.settings.fee_bump_percent);
+        let signed: SignedTx = self.signer.sign_transaction(self.web3, tx.clone()).await?;
+        let replaces: H256 = pending.tx_hash;
+
+        pending.replacements += 1;
+        pending.sent_at = Instant::now();
+        if let Err(error) = self.web3.eth().send_raw_transaction(signed.raw_transaction.clone()).await {
+            // Usually an earlier attempt got mined in the meantime, the next poll finds it.
+            self.journal.record(&pending.label, pending.nonce, Some(signed.transaction_hash), JournalEvent::Failed { error: format!("{:?}", error) });
+            return Ok(());
+        }
+        self.journal.record(&pending.label, pending.nonce, Some(signed.transaction_hash), JournalEvent::submitted(pending.replacements, Some(replaces), &tx));
+        println!("{} (nonce {}) replaced by {:?} with higher fees", pending.label, pending.nonce, signed.transaction_hash);
+
+        pending.tx_hash = signed.transaction_hash;
+        pending.hashes.push(signed.transaction_hash);
+        pending.tx = tx;
+        pending.raw = signed.raw_transaction;
+        Ok(())
+    }
+    // A transaction the node no longer knows is broadcast again. If the node refuses it,
+    // a zero value transfer to ourselves takes its nonce so the later ones can be mined.
+    async fn recover_dropped(&self, pending: &mut PendingTransaction) -> DeployResult<()> {
+
+        let known = self.web3.eth().transaction(TransactionId::Hash(pending.tx_hash)).await.map_err(DeployError::rpc)?;
+        if known.is_some() {
+            return Ok(());
+        }
+        println!("{} (nonce {}) was dropped, broadcasting it again", pending.label, pending.nonce);
+        if self.web3.eth().send_raw_transaction(pending.raw.clone()).await.is_ok() {
+            self.journal.record(&pending.label, pending.nonce, Some(pending.tx_hash), JournalEvent::Rebroadcast);
+            return Ok(());
+        }
+
+        let filler = TransactionParameters {
+            nonce: Some(pending.nonce),
+            to: Some(self.nonces.address),
+            gas: U256::from(21000),
+            value: U256::zero(),
+            data: Bytes::default(),
+            ..bump_fees(&pending.tx, self.settings.fee_bump_percent)
+        };
+        let signed: SignedTx = self.signer.sign_transaction(self.web3, filler).await?;
+        self.web3.eth().send_raw_transaction(signed.raw_transaction).await.map_err(DeployError::rpc)?;
+        self.journal.record(&pending.label, pending.nonce, Some(signed.transaction_hash), JournalEvent::GapFilled { dropped: pending.tx_hash });
+        println!("Filled the nonce gap at {} with {:?}", pending.nonce, signed.transaction_hash);
+
+        pending.filler = Some(signed.transaction_hash);
+        pending.hashes.push(signed.transaction_hash);
+        Ok(())
+    }
+    async fn mined(&self, pending: &PendingTransaction) -> DeployResult<Option<TransactionReceipt>> {
+        for hash in pending.hashes.iter() {
+            if let Some(receipt) = self.web3.eth().transaction_receipt(*hash).await.map_err(DeployError::rpc)? {
+                return Ok(Some(receipt));
+            }
+        }
+        Ok(None)
+    }
+    // Checks one transaction; returns its result once it is settled one way or another.
+    async fn poll(&self, pending: &mut PendingTransaction, polls: u32) -> Option<DeployResult<TransactionReceipt>> {
+
+        let fail = |error: DeployError, pending: &PendingTransaction| error.at_step(&pending.label).with_tx(pending.tx_hash);
+
+        match self.mined(pending).await {
+            Ok(Some(receipt)) => {
+                self.journal.record(
+                    &pending.label,
+                    pending.nonce,
+                    Some(receipt.transaction_hash),
+                    JournalEvent::Mined { block: receipt.block_number, status: receipt.status, gas_used: receipt.gas_used },
+                );
+                if pending.filler == Some(receipt.transaction_hash) {
+                    let message = format!("transaction was dropped, its nonce {} was filled by {:?}", pending.nonce, receipt.transaction_hash);
+                    return Some(Err(fail(DeployError::rpc(message), pending)));
+                }
+                return Some(Ok(receipt));
+            },
+            Ok(None) => {},
+            Err(error) => return Some(Err(fail(error, pending))),
+        }
+
+        let timeout: Duration = self.settings.timeout(&pending.label);
+        if pending.sent_at.elapsed() >= timeout {
+            self.journal.record(&pending.label, pending.nonce, Some(pending.tx_hash), JournalEvent::TimedOut { waited_secs: pending.sent_at.elapsed().as_secs() });
+            if pending.filler.is_some() || pending.replacements >= self.settings.max_replacements {
+                let attempts: u32 = pending.hashes.len() as u32;
+                self.journal.record(&pending.label, pending.nonce, Some(pending.tx_hash), JournalEvent::Abandoned { attempts });
+                return Some(Err(fail(DeployError::new(ErrorKind::Stuck { nonce: pending.nonce, attempts }), pending)));
+            }
+            if let Err(error) = self.replace(pending).await {
+                return Some(Err(fail(error, pending)));
+            }
+        } else if pending.filler.is_none() && polls % GAP_CHECK_POLLS == 0 {
+            if let Err(error) = self.recover_dropped(pending).await {
+                return Some(Err(fail(error, pending)));
+            }
+        }
+        None
+    }
+    // Waits for every submitted transaction together. Results come back in submission order;
+    // one failed transaction does not hide the receipts of the others.
+    pub async fn wait(&mut self) -> Vec<(PendingTransaction, DeployResult<TransactionReceipt>)> {
+
+        let mut pending: Vec<PendingTransaction> = std::mem::take(&mut self.pending);
+        let mut results: Vec<Option<DeployResult<TransactionReceipt>>> = pending.iter().map(|_| None).collect();
+        let mut polls: u32 = 0;
+
+        while results.iter().any(|r| r.is_none()) {
+            tokio::time::sleep(POLL_INTERVAL).await;
+            polls += 1;
+
+            for (i, tx) in pending.iter_mut().enumerate() {
+                if results[i].is_none() {
+                    results[i] = self.poll(tx, polls).await;
+                }
+            }
+        }
+
+        pending.into_iter()
+            .zip(results.into_iter().flatten())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{ AtomicU64, Ordering };
+    use serde_json::{ json, Value };
+    use super::*;
+    use crate::signing::LocalSigner;
+    use crate::test_node::TestNode;
+
+    const KEY: &str = "4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318";
+    const CHAIN_ID: u64 = 245022926;
+
+    fn legacy_tx(to: Address, gas_price: u64) -> TransactionParameters {
+        TransactionParameters {
+            to: Some(to),
+            gas: U256::from(100_000),
+            gas_price: Some(U256::from(gas_price)),
+            chain_id: Some(CHAIN_ID),
+            ..TransactionParameters::default()
+        }
+    }
+
+    fn journal(name: &str) -> Journal {
+        let path = std::env::temp_dir().join(format!("nonce-test-{}-{}.jsonl", name, std::process::id()));
+        Journal::new(path.to_str().unwrap())
+    }
+
+    // A node that never mines anything and has forgotten every transaction. The first
+    // broadcast is accepted; the rebroadcast only when `accept_rebroadcast` is set.
+    fn dropping_node(accept_rebroadcast: bool) -> TestNode {
+        let sent: AtomicU64 = AtomicU64::new(0);
+        TestNode::start(move |method, _| match method {
+            "eth_getTransactionCount" => Ok(json!("0x7")),
+            "eth_getTransactionReceipt" | "eth_getTransactionByHash" => Ok(Value::Null),
+            "eth_sendRawTransaction" => {
+                if sent.fetch_add(1, Ordering::SeqCst) == 1 && !accept_rebroadcast {
+                    return Err(json!({ "code": -32000, "message": "nonce too low" }));
+                }
+                Ok(json!(H256::zero()))
+            },
+            _ => Err(json!({ "code": -32601, "message": "method not found" })),
+        })
+    }
+
+    #[test]
+    fn bumps_every_fee_by_the_percentage() {
+        let tx = TransactionParameters {
+            max_fee_per_gas: Some(U256::from(2_000)),
+            max_priority_fee_per_gas: Some(U256::from(100)),
+            ..legacy_tx(Address::repeat_byte(0x22), 100)
+        };
+        let bumped: TransactionParameters = bump_fees(&tx, 10);
+        assert_eq!(bumped.gas_price, Some(U256::from(110)));
+        assert_eq!(bumped.max_fee_per_gas, Some(U256::from(2_200)));
+        assert_eq!(bumped.max_priority_fee_per_gas, Some(U256::from(110)));
+        assert_eq!(bumped.gas, tx.gas);
+        assert_eq!(bumped.to, tx.to);
+    }
+
+    #[test]
+    fn bumps_small_fees_by_at_least_one_wei() {
+        // 5 * 110 / 100 and 1 * 115 / 100 round down to the fee itself
+        assert_eq!(bump_fees(&legacy_tx(Address::zero(), 5), 10).gas_price, Some(U256::from(6)));
+        assert_eq!(bump_fees(&legacy_tx(Address::zero(), 1), 15).gas_price, Some(U256::from(2)));
+        assert_eq!(bump_fees(&legacy_tx(Address::zero(), 0), 15).gas_price, Some(U256::from(1)));
+        let unset: TransactionParameters = bump_fees(&legacy_tx(Address::zero(), 1), 15);
+        assert_eq!(unset.max_fee_per_gas, None);
+        assert_eq!(unset.max_priority_fee_per_gas, None);
+    }
+
+    #[tokio::test]
+    async fn rebroadcasts_a_dropped_transaction() {
+        let node: TestNode = dropping_node(true);
+        let web3 = node.web3();
+        let signer: LocalSigner = LocalSigner::from_hex(KEY).unwrap();
+        let nonces: NonceManager = NonceManager::new(signer.address());
+        let journal: Journal = journal("rebroadcast");
+        let settings: SubmissionSettings = SubmissionSettings::default();
+        let mut pipeline: Pipeline = Pipeline::new(&web3, &signer, &nonces, &journal, &settings);
+
+        pipeline.submit("transfer", legacy_tx(Address::repeat_byte(0x22), 100)).await.unwrap();
+        let mut pending: PendingTransaction = pipeline.pending.pop().unwrap();
+
+        assert!(pipeline.poll(&mut pending, GAP_CHECK_POLLS).await.is_none());
+        let sent = node.calls("eth_sendRawTransaction");
+        assert_eq!(sent.len(), 2);
+        assert_eq!(sent[0], sent[1]);
+        assert_eq!(pending.filler, None);
+        assert_eq!(pending.hashes.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn fills_the_nonce_of_a_dropped_transaction_the_node_refuses() {
+        let node: TestNode = dropping_node(false);
+        let web3 = node.web3();
+        let signer: LocalSigner = LocalSigner::from_hex(KEY).unwrap();
+        let nonces: NonceManager = NonceManager::new(signer.address());
+        let journal: Journal = journal("filler");
+        let settings: SubmissionSettings = SubmissionSettings::default();
+        let mut pipeline: Pipeline = Pipeline::new(&web3, &signer, &nonces, &journal, &settings);
+
+        pipeline.submit("transfer", legacy_tx(Address::repeat_byte(0x22), 100)).await.unwrap();
+        let mut pending: PendingTransaction = pipeline.pending.pop().unwrap();
+
+        // the node is only asked about dropped transactions every GAP_CHECK_POLLS polls
+        for polls in 1..GAP_CHECK_POLLS {
+            assert!(pipeline.poll(&mut pending, polls).await.is_none());
+        }
+        assert!(node.calls("eth_getTransactionByHash").is_empty());
+        assert!(pipeline.poll(&mut pending, GAP_CHECK_POLLS).await.is_none());
+        assert_eq!(node.calls("eth_getTransactionByHash").len(), 1);
+
+        // a zero value self transfer with the dropped nonce and bumped fees
+        let filler = TransactionParameters {
+            nonce: Some(U256::from(7)),
+            to: Some(signer.address()),
+            gas: U256::from(21000),
+            gas_price: Some(U256::from(115)),
+            chain_id: Some(CHAIN_ID),
+            ..TransactionParameters::default()
+        };
+        let expected: SignedTx = signer.sign_transaction(&web3, filler).await.unwrap();
+        let sent = node.calls("eth_sendRawTransaction");
+        assert_eq!(sent.len(), 3);
+        assert_eq!(sent[2], json!([expected.raw_transaction]));
+        assert_eq!(pending.filler, Some(expected.transaction_hash));
+        assert_eq!(pending.hashes, vec![pending.tx_hash, expected.transaction_hash]);
+
+        // once filled, the gap is not checked again
+        assert!(pipeline.poll(&mut pending, 2 * GAP_CHECK_POLLS).await.is_none());
+        assert_eq!(node.calls("eth_getTransactionByHash").len(), 1);
+    }
+
+    // A node whose pending transaction count is whatever `count` holds.
+    fn counting_node(count: Arc<AtomicU64>) -> TestNode {
+        TestNode::start(move |method, _| match method {
+            "eth_getTransactionCount" => Ok(json!(format!("{:#x}", count.load(Ordering::SeqCst)))),
+            _ => Err(json!({ "code": -32601, "message": "method not found" })),
+        })
+    }
+
+    #[tokio::test]
+    async fn allocates_consecutive_nonces_from_the_pending_count() {
+        let node: TestNode = counting_node(Arc::new(AtomicU64::new(5)));
+        let web3 = node.web3();
+        let nonces: NonceManager = NonceManager::new(Address::repeat_byte(0x11));
+
+        assert_eq!(nonces.peek(&web3).await.unwrap(), U256::from(5));
+        assert_eq!(nonces.next(&web3).await.unwrap(), U256::from(5));
+        assert_eq!(nonces.next(&web3).await.unwrap(), U256::from(6));
+        assert_eq!(nonces.peek(&web3).await.unwrap(), U256::from(7));
+        assert_eq!(nonces.next(&web3).await.unwrap(), U256::from(7));
+
+        let calls = node.calls("eth_getTransactionCount");
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0], json!([format!("{:?}", Address::repeat_byte(0x11)), "pending"]));
+    }
+
+    #[tokio::test]
+    async fn reset_reads_the_count_from_the_node_again() {
+        let count: Arc<AtomicU64> = Arc::new(AtomicU64::new(3));
+        let node: TestNode = counting_node(count.clone());
+        let web3 = node.web3();
+        let nonces: NonceManager = NonceManager::new(Address::repeat_byte(0x11));
+
+        assert_eq!(nonces.next(&web3).await.unwrap(), U256::from(3));
+        assert_eq!(nonces.next(&web3).await.unwrap(), U256::from(4));
+        // the transaction with nonce 4 never reached the node
+        count.store(4, Ordering::SeqCst);
+        nonces.reset();
+        assert_eq!(nonces.next(&web3).await.unwrap(), U256::from(4));
+        assert_eq!(node.calls("eth_getTransactionCount").len(), 2);
+    }
+}
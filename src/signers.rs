@@ -0,0 +1,131 @@
+use std::collections::BTreeMap;
+
+use web3::types::Address;
+
+use crate::config::{ KeySource, RoleConfig, RoleFunding };
+use crate::error::{ DeployError, DeployResult };
+use crate::nonce::NonceManager;
+use crate::signing::{ Signer, load_signer };
+
+pub const DEPLOYER: &str = "deployer";
+pub const LIQUIDITY_PROVIDER: &str = "liquidity_provider";
+pub const VOTER: &str = "voter";
+pub const TRADER: &str = "trader";
+
+pub struct RoleSigner {
+    pub role: String,
+    pub signer: Box<dyn Signer>,
+    pub address: Address,
+    pub nonces: NonceManager,
+    pub funding: RoleFunding,
+}
+
+impl RoleSigner {
+    fn load(role: &str, source: &KeySource, funding: RoleFunding) -> DeployResult<RoleSigner> {
+        let signer: Box<dyn Signer> = load_signer(source).map_err(|e| e.at_step(role))?;
+        let address: Address = signer.address();
+        Ok(RoleSigner {
+            role: role.to_string(),
+            signer,
+            address,
+            nonces: NonceManager::new(address),
+            funding,
+        })
+    }
+}
+
+// The deployer plus every role configured for the profile, each with its own nonces.
+pub struct SignerRegistry {
+    signers: BTreeMap<String,RoleSigner>,
+}
+
+impl SignerRegistry {
+    pub fn load(deployer: &KeySource, roles: &BTreeMap<String,RoleConfig>) -> DeployResult<SignerRegistry> {
+
+        let mut signers: BTreeMap<String,RoleSigner> = BTreeMap::new();
+        signers.insert(DEPLOYER.to_string(), RoleSigner::load(DEPLOYER, deployer, RoleFunding::default())?);
+
+        for (role, config) in roles.iter() {
+            if role == DEPLOYER {
+                return Err(DeployError::config("the deployer role is taken from the profile key and cannot be configured"));
+            }
+            let signer: RoleSigner = RoleSigner::load(role, &config.key, config.funding.clone())?;
+            if let Some(other) = signers.values().find(|s| s.address == signer.address) {
+                println!("Warning: roles '{}' and '{}' share the same account", other.role, role);
+            }
+            signers.insert(role.clone(), signer);
+        }
+        Ok(SignerRegistry { signers })
+    }
+    pub fn deployer(&self) -> &RoleSigner {
+        &self.signers[DEPLOYER]
+    }
+    pub fn get(&self, role: &str) -> DeployResult<&RoleSigner> {
+        self.signers.get(role)
+            .ok_or_else(|| {
+                let known: Vec<&str> = self.signers.keys().map(|k| k.as_str()).collect();
+                DeployError::config(format!("unknown signer role '{}', known roles: {}", role, known.join(", ")))
+            })
+    }
+    pub fn contains(&self, role: &str) -> bool {
+        self.signers.contains_key(role)
+    }
+    pub fn iter(&self) -> impl Iterator<Item = &RoleSigner> {
+        self.signers.values()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn remote(byte: u8) -> KeySource {
+        KeySource::Remote { url: "http://127.0.0.1:1".to_string(), address: Address::repeat_byte(byte) }
+    }
+
+    fn role(byte: u8) -> RoleConfig {
+        RoleConfig { key: remote(byte), funding: RoleFunding::default() }
+    }
+
+    #[test]
+    fn loads_the_deployer_and_every_role() {
+        let roles: BTreeMap<String,RoleConfig> =
+            vec![(LIQUIDITY_PROVIDER.to_string(), role(0x02)), (VOTER.to_string(), role(0x03)), (TRADER.to_string(), role(0x02))]
+                .into_iter()
+                .collect();
+        let signers: SignerRegistry = SignerRegistry::load(&remote(0x01), &roles).unwrap();
+
+        assert_eq!(signers.deployer().role, DEPLOYER);
+        assert_eq!(signers.deployer().address, Address::repeat_byte(0x01));
+        assert_eq!(signers.get(VOTER).unwrap().address, Address::repeat_byte(0x03));
+        // roles may share an account, they only get a warning
+        assert_eq!(signers.get(TRADER).unwrap().address, signers.get(LIQUIDITY_PROVIDER).unwrap().address);
+        assert_eq!(signers.iter().count(), 4);
+        assert!(signers.contains(VOTER) && !signers.contains("auditor"));
+    }
+
+    #[test]
+    fn rejects_a_configured_deployer_role() {
+        let roles: BTreeMap<String,RoleConfig> = vec![(DEPLOYER.to_string(), role(0x02))].into_iter().collect();
+        let error: DeployError = SignerRegistry::load(&remote(0x01), &roles).err().unwrap();
+        assert!(error.to_string().contains("the deployer role is taken from the profile key"), "{}", error);
+    }
+
+    #[test]
+    fn reports_the_role_whose_key_is_missing() {
+        let missing = KeySource::Env("SIGNERS_TEST_KEY_THAT_IS_NOT_SET".to_string());
+        let error: DeployError = SignerRegistry::load(&missing, &BTreeMap::new()).err().unwrap();
+        assert_eq!(error.step.as_deref(), Some(DEPLOYER));
+
+        let roles: BTreeMap<String,RoleConfig> = vec![(VOTER.to_string(), RoleConfig { key: missing, funding: RoleFunding::default() })].into_iter().collect();
+        let error: DeployError = SignerRegistry::load(&remote(0x01), &roles).err().unwrap();
+        assert_eq!(error.step.as_deref(), Some(VOTER));
+    }
+
+    #[test]
+    fn lists_the_known_roles_for_an_unknown_one() {
+        let signers: SignerRegistry = SignerRegistry::load(&remote(0x01), &vec![(VOTER.to_string(), role(0x03))].into_iter().collect()).unwrap();
+        let error: DeployError = signers.get(TRADER).err().unwrap();
+        assert!(error.to_string().contains("unknown signer role 'trader', known roles: deployer, voter"), "{}", error);
+    }
+}
@@ -0,0 +1,319 @@
+use std::future::Future;
+use std::pin::Pin;
+
+use secp256k1::SecretKey;
+use serde_json::{ json, Value };
+use web3::Transport;
+use web3::types::{ Address, Bytes, TransactionParameters, H256, U64 };
+use web3::signing::{ keccak256, Key, SecretKeyRef };
+use zeroize::Zeroizing;
+
+use crate::config::KeySource;
+use crate::error::{ DeployError, DeployResult, ErrorKind };
+
+pub type SignFuture<'a> = Pin<Box<dyn Future<Output = DeployResult<SignedTx>> + 'a>>;
+
+pub struct SignedTx {
+    pub raw_transaction: Bytes,
+    pub transaction_hash: H256,
+}
+
+// Everything that sends transactions signs them through this trait, so a key never
+// has to be present in this process.
+pub trait Signer {
+    fn address(&self) -> Address;
+    // `tx` always carries its nonce and fees; the chain id is filled in when missing.
+    fn sign_transaction<'a>(&'a self, web3: &'a web3::Web3<web3::transports::Http>, tx: TransactionParameters) -> SignFuture<'a>;
+}
+
+// Keeps only the wiped-on-drop key bytes, the secp256k1 key is rebuilt for each signature.
+pub struct LocalSigner {
+    key: Zeroizing<[u8; 32]>,
+    address: Address,
+}
+
+impl LocalSigner {
+    pub fn from_hex(key_hex: &str) -> DeployResult<LocalSigner> {
+        let mut key: Zeroizing<[u8; 32]> = Zeroizing::new([0; 32]);
+        hex::decode_to_slice(key_hex, &mut key[..]).map_err(DeployError::signing)?;
+        let address: Address = SecretKeyRef::new(&secret_key(&key)?).address();
+        Ok(LocalSigner { key, address })
+    }
+}
+
+fn secret_key(key: &[u8; 32]) -> DeployResult<SecretKey> {
+    SecretKey::from_slice(key).map_err(DeployError::signing)
+}
+
+impl Signer for LocalSigner {
+    fn address(&self) -> Address {
+        self.address
+    }
+    fn sign_transaction<'a>(&'a self, web3: &'a web3::Web3<web3::transports::Http>, tx: TransactionParameters) -> SignFuture<'a> {
+        Box::pin(async move {
+            let key: SecretKey = secret_key(&self.key)?;
+            let signed = web3.accounts().sign_transaction(tx, &key).await.map_err(DeployError::signing)?;
+            Ok(SignedTx {
+                raw_transaction: signed.raw_transaction,
+                transaction_hash: signed.transaction_hash,
+            })
+        })
+    }
+}
+
+// Asks a separate process to sign over JSON-RPC `eth_signTransaction`. Any node with
+// an unlocked account (anvil, ganache, geth --dev) can stand in for the real signer.
+pub struct RemoteSigner {
+    url: String,
+    transport: web3::transports::Http,
+    address: Address,
+}
+
+impl RemoteSigner {
+    pub fn new(url: &str, address: Address) -> DeployResult<RemoteSigner> {
+        let transport = web3::transports::Http::new(url).map_err(|e| DeployError::config(format!("invalid remote signer url {}: {:?}", url, e)))?;
+        Ok(RemoteSigner { url: url.to_string(), transport, address })
+    }
+    fn error(&self, message: String) -> DeployError {
+        DeployError::new(ErrorKind::Signing(format!("remote signer {}: {}", self.url, message)))
+    }
+}
+
+impl Signer for RemoteSigner {
+    fn address(&self) -> Address {
+        self.address
+    }
+    fn sign_transaction<'a>(&'a self, web3: &'a web3::Web3<web3::transports::Http>, tx: TransactionParameters) -> SignFuture<'a> {
+        Box::pin(async move {
+            let chain_id: u64 =
+                match tx.chain_id {
+                    Some(chain_id) => chain_id,
+                    None => web3.eth().chain_id().await.map_err(DeployError::rpc)?.as_u64(),
+                };
+
+            let mut request: Value = json!({
+                "from": self.address,
+                "gas": tx.gas,
+                "value": tx.value,
+                "data": tx.data,
+                "nonce": tx.nonce,
+                "chainId": U64::from(chain_id),
+            });
+            if let Some(to) = tx.to {
+                request["to"] = json!(to);
+            }
+            if let Some(gas_price) = tx.gas_price {
+                request["gasPrice"] = json!(gas_price);
+            }
+            if let Some(transaction_type) = tx.transaction_type {
+                request["type"] = json!(transaction_type);
+            }
+            if let Some(max_fee_per_gas) = tx.max_fee_per_gas {
+                request["maxFeePerGas"] = json!(max_fee_per_gas);
+            }
+            if let Some(max_priority_fee_per_gas) = tx.max_priority_fee_per_gas {
+                request["maxPriorityFeePerGas"] = json!(max_priority_fee_per_gas);
+            }
+
+            let response: Value =
+                self.transport.execute("eth_signTransaction", vec![request])
+                    .await
+                    .map_err(|e| self.error(format!("{:?}", e)))?;
+
+            // geth answers with { raw, tx }, other signers with the raw transaction alone
+            let raw_hex: &str =
+                response.as_str()
+                    .or_else(|| response["raw"].as_str())
+                    .ok_or_else(|| self.error(format!("unexpected eth_signTransaction response {}", response)))?;
+            let raw: Vec<u8> = hex::decode(raw_hex.trim_start_matches("0x")).map_err(|e| self.error(format!("invalid raw transaction: {}", e)))?;
+
+            Ok(SignedTx {
+                transaction_hash: H256::from(keccak256(&raw)),
+                raw_transaction: Bytes(raw),
+            })
+        })
+    }
+}
+
+pub fn load_signer(source: &KeySource) -> DeployResult<Box<dyn Signer>> {
+    match source {
+        KeySource::Remote { url, address } => Ok(Box::new(RemoteSigner::new(url, *address)?)),
+        _ => {
+            let key: Zeroizing<String> = source.read()?;
+            Ok(Box::new(LocalSigner::from_hex(&key)?))
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use web3::types::U256;
+    use super::*;
+    use crate::test_node::TestNode;
+
+    // The EIP-155 example transaction and its key.
+    const KEY: &str = "4646464646464646464646464646464646464646464646464646464646464646";
+    const SIGNED: &str = "f86c098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a76400008025a028ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276a067cbe9d8997f761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83";
+
+    fn eip155_tx() -> TransactionParameters {
+        TransactionParameters {
+            nonce: Some(U256::from(9)),
+            gas_price: Some(U256::from(20_000_000_000u64)),
+            gas: U256::from(21000),
+            to: Some(Address::repeat_byte(0x35)),
+            value: U256::exp10(18),
+            chain_id: Some(1),
+            ..TransactionParameters::default()
+        }
+    }
+
+    // Just enough RLP for a legacy transaction: a list of byte strings.
+    fn rlp_length(offset: u8, len: usize) -> Vec<u8> {
+        if len <= 55 {
+            return vec![offset + len as u8];
+        }
+        let bytes: Vec<u8> = len.to_be_bytes().iter().copied().skip_while(|b| *b == 0).collect();
+        let mut header: Vec<u8> = vec![offset + 55 + bytes.len() as u8];
+        header.extend(bytes);
+        header
+    }
+
+    fn rlp_encode(items: &[Vec<u8>]) -> Vec<u8> {
+        let mut payload: Vec<u8> = Vec::new();
+        for item in items {
+            if item.len() != 1 || item[0] >= 0x80 {
+                payload.extend(rlp_length(0x80, item.len()));
+            }
+            payload.extend(item);
+        }
+        let mut encoded: Vec<u8> = rlp_length(0xc0, payload.len());
+        encoded.extend(payload);
+        encoded
+    }
+
+    fn rlp_decode(encoded: &[u8]) -> Vec<Vec<u8>> {
+        let read_length = |bytes: &[u8]| bytes.iter().fold(0usize, |len, b| (len << 8) | *b as usize);
+        let mut at: usize = if encoded[0] > 0xf7 { 1 + (encoded[0] - 0xf7) as usize } else { 1 };
+        let mut items: Vec<Vec<u8>> = Vec::new();
+        while at < encoded.len() {
+            let (start, len) =
+                match encoded[at] {
+                    b @ 0x00..=0x7f => { items.push(vec![b]); at += 1; continue; },
+                    b @ 0x80..=0xb7 => (at + 1, (b - 0x80) as usize),
+                    b => {
+                        let size: usize = (b - 0xb7) as usize;
+                        (at + 1 + size, read_length(&encoded[at + 1..at + 1 + size]))
+                    },
+                };
+            items.push(encoded[start..start + len].to_vec());
+            at = start + len;
+        }
+        items
+    }
+
+    // Recovers the sender of a signed EIP-155 legacy transaction.
+    fn recover_sender(raw: &[u8], chain_id: u64) -> Address {
+        let items: Vec<Vec<u8>> = rlp_decode(raw);
+        assert_eq!(items.len(), 9);
+        let mut unsigned: Vec<Vec<u8>> = items[..6].to_vec();
+        unsigned.push(chain_id.to_be_bytes().iter().copied().skip_while(|b| *b == 0).collect());
+        unsigned.push(Vec::new());
+        unsigned.push(Vec::new());
+
+        let v: u64 = items[6].iter().fold(0u64, |v, b| (v << 8) | *b as u64);
+        let mut signature: Vec<u8> = vec![0; 64];
+        signature[32 - items[7].len()..32].copy_from_slice(&items[7]);
+        signature[64 - items[8].len()..].copy_from_slice(&items[8]);
+        web3::signing::recover(&keccak256(&rlp_encode(&unsigned)), &signature, (v - 35 - 2 * chain_id) as i32).unwrap()
+    }
+
+    fn unreachable_node() -> TestNode {
+        TestNode::start(|_, _| Err(json!({ "code": -32601, "message": "method not found" })))
+    }
+
+    #[tokio::test]
+    async fn local_signer_signs_the_eip155_example() {
+        let node: TestNode = unreachable_node();
+        let signer: LocalSigner = LocalSigner::from_hex(KEY).unwrap();
+        let signed: SignedTx = signer.sign_transaction(&node.web3(), eip155_tx()).await.unwrap();
+
+        assert_eq!(hex::encode(&signed.raw_transaction.0), SIGNED);
+        assert_eq!(signed.transaction_hash, H256::from(keccak256(&signed.raw_transaction.0)));
+        assert_eq!(recover_sender(&signed.raw_transaction.0, 1), signer.address());
+    }
+
+    #[tokio::test]
+    async fn local_signer_round_trips_through_recovery() {
+        let node: TestNode = unreachable_node();
+        let signer: LocalSigner = LocalSigner::from_hex("4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318").unwrap();
+        let tx = TransactionParameters {
+            nonce: Some(U256::from(0x1234)),
+            data: Bytes(vec![0xab; 100]),
+            chain_id: Some(245022926),
+            ..eip155_tx()
+        };
+        let signed: SignedTx = signer.sign_transaction(&node.web3(), tx).await.unwrap();
+
+        let items: Vec<Vec<u8>> = rlp_decode(&signed.raw_transaction.0);
+        assert_eq!(items[0], vec![0x12, 0x34]);
+        assert_eq!(items[5], vec![0xab; 100]);
+        assert_eq!(recover_sender(&signed.raw_transaction.0, 245022926), signer.address());
+        assert!(node.calls("eth_chainId").is_empty());
+    }
+
+    #[tokio::test]
+    async fn remote_signer_sends_the_transaction_to_eth_sign_transaction() {
+        let node: TestNode = TestNode::start(|method, _| match method {
+            "eth_chainId" => Ok(json!("0x1")),
+            "eth_signTransaction" => Ok(json!(format!("0x{}", SIGNED))),
+            _ => Err(json!({ "code": -32601, "message": "method not found" })),
+        });
+        let from: Address = Address::repeat_byte(0x9d);
+        let signer: RemoteSigner = RemoteSigner::new(node.url(), from).unwrap();
+        let tx = TransactionParameters { chain_id: None, ..eip155_tx() };
+        let signed: SignedTx = signer.sign_transaction(&node.web3(), tx).await.unwrap();
+
+        assert_eq!(node.calls("eth_chainId").len(), 1);
+        assert_eq!(
+            node.calls("eth_signTransaction"),
+            vec![json!([{
+                "from": from,
+                "to": Address::repeat_byte(0x35),
+                "gas": "0x5208",
+                "gasPrice": "0x4a817c800",
+                "value": "0xde0b6b3a7640000",
+                "data": "0x",
+                "nonce": "0x9",
+                "chainId": "0x1",
+            }])],
+        );
+        assert_eq!(hex::encode(&signed.raw_transaction.0), SIGNED);
+        assert_eq!(signed.transaction_hash, H256::from(keccak256(&signed.raw_transaction.0)));
+    }
+
+    #[tokio::test]
+    async fn remote_signer_accepts_the_geth_response() {
+        let node: TestNode = TestNode::start(|method, _| match method {
+            "eth_signTransaction" => Ok(json!({ "raw": format!("0x{}", SIGNED), "tx": {} })),
+            _ => Err(json!({ "code": -32601, "message": "method not found" })),
+        });
+        let signer: RemoteSigner = RemoteSigner::new(node.url(), Address::repeat_byte(0x9d)).unwrap();
+        let signed: SignedTx = signer.sign_transaction(&node.web3(), eip155_tx()).await.unwrap();
+
+        assert_eq!(hex::encode(&signed.raw_transaction.0), SIGNED);
+        assert!(node.calls("eth_chainId").is_empty());
+    }
+
+    #[tokio::test]
+    async fn remote_signer_reports_refusals_as_signing_errors() {
+        let node: TestNode = TestNode::start(|_, _| Err(json!({ "code": -32000, "message": "unknown account" })));
+        let signer: RemoteSigner = RemoteSigner::new(node.url(), Address::repeat_byte(0x9d)).unwrap();
+        let error: DeployError = signer.sign_transaction(&node.web3(), eip155_tx()).await.err().unwrap();
+
+        match error.kind {
+            ErrorKind::Signing(message) => assert!(message.contains("unknown account"), "{}", message),
+            kind => panic!("expected a signing error, got {}", kind),
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use std::io::{ BufRead, BufReader, Read, Write };
+use std::net::{ TcpListener, TcpStream };
+use std::sync::{ Arc, Mutex };
+
+use serde_json::{ json, Value };
+
+type Handler = dyn Fn(&str, &Value) -> Result<Value, Value> + Send + Sync;
+
+// A JSON-RPC node on a local port for tests. Every request is answered by the handler
+// and recorded, so tests can check what was sent to the node.
+pub struct TestNode {
+    url: String,
+    calls: Arc<Mutex<Vec<(String, Value)>>>,
+}
+
+impl TestNode {
+    // The handler gets the method and params and returns the result, or the error object.
+    pub fn start(handler: impl Fn(&str, &Value) -> Result<Value, Value> + Send + Sync + 'static) -> TestNode {
+        let listener: TcpListener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url: String = format!("http://{}", listener.local_addr().unwrap());
+        let calls: Arc<Mutex<Vec<(String, Value)>>> = Arc::new(Mutex::new(Vec::new()));
+        let recorded = calls.clone();
+        let handler: Arc<Handler> = Arc::new(handler);
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                serve(stream, &*handler, &recorded);
+            }
+        });
+        TestNode { url, calls }
+    }
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+    pub fn web3(&self) -> web3::Web3<web3::transports::Http> {
+        web3::Web3::new(web3::transports::Http::new(&self.url).unwrap())
+    }
+    // Params of every request made with `method`, in order.
+    pub fn calls(&self, method: &str) -> Vec<Value> {
+        self.calls.lock().unwrap().iter()
+            .filter(|(m, _)| m == method)
+            .map(|(_, params)| params.clone())
+            .collect()
+    }
+}
+
+fn serve(stream: TcpStream, handler: &Handler, calls: &Mutex<Vec<(String, Value)>>) {
+
+    let mut reader = BufReader::new(&stream);
+    let mut length: usize = 0;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).unwrap_or(0) == 0 {
+            return;
+        }
+        let line: &str = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+    let mut body: Vec<u8> = vec![0; length];
+    if reader.read_exact(&mut body).is_err() {
+        return;
+    }
+
+    let request: Value = serde_json::from_slice(&body).unwrap_or(Value::Null);
+    let method: String = request["method"].as_str().unwrap_or_default().to_string();
+    let params: Value = request["params"].clone();
+    calls.lock().unwrap().push((method.clone(), params.clone()));
+
+    let response: Value =
+        match handler(&method, &params) {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": request["id"], "result": result }),
+            Err(error) => json!({ "jsonrpc": "2.0", "id": request["id"], "error": error }),
+        };
+    let response: String = response.to_string();
+    let _ = write!(
+        &stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.len(),
+        response,
+    );
+}
@@ -0,0 +1,306 @@
+use serde::{ Deserialize, Serialize };
+
+use web3::types::{ Address, Bytes, H256, U256 };
+use web3::ethabi::Token;
+use web3::contract::Contract;
+use web3::contract::tokens::{ Detokenize, Tokenize };
+use web3::signing::keccak256;
+
+use web3_tools::AsEip55;
+
+use crate::artifact::ArtifactRegistry;
+use crate::address_book::write_atomically;
+use crate::config::MAX_SECONDS_TIMESTAMP;
+use crate::governance::EncodedAction;
+use crate::liquidity::NeonswapEnvironment;
+use crate::error::{ DeployError, DeployResult };
+
+// Seconds added on top of the delay so the ETA is still far enough out when the
+// queueing transaction is mined.
+const ETA_MARGIN_SECS: u64 = 120;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QueueStatus {
+    Queued,
+    Executed,
+    Canceled,
+}
+
+#[derive(Clone, Debug)]
+#[derive(Deserialize, Serialize)]
+pub struct QueuedTransaction {
+    pub hash: H256,
+    pub target: Address,
+    pub value: U256,
+    pub signature: String,
+    pub data: Bytes,
+    pub eta: U256,
+    pub status: QueueStatus,
+}
+
+// keccak256(abi.encode(target, value, signature, data, eta)), as in Timelock.queueTransaction
+pub fn transaction_hash(target: Address, value: U256, signature: &str, data: &[u8], eta: U256) -> H256 {
+    let encoded: Vec<u8> = web3::ethabi::encode(&[
+        Token::Address(target),
+        Token::Uint(value),
+        Token::String(signature.to_string()),
+        Token::Bytes(data.to_vec()),
+        Token::Uint(eta),
+    ]);
+    H256::from(keccak256(&encoded))
+}
+
+impl QueuedTransaction {
+    fn params(&self) -> Vec<Token> {
+        vec![
+            Token::Address(self.target),
+            Token::Uint(self.value),
+            Token::String(self.signature.clone()),
+            Token::Bytes(self.data.0.clone()),
+            Token::Uint(self.eta),
+        ]
+    }
+}
+
+// Local record of what was queued, the Timelock itself only stores the hashes.
+#[derive(Clone, Debug, Default)]
+#[derive(Deserialize, Serialize)]
+pub struct TimelockQueue {
+    #[serde(skip)]
+    path: String,
+    #[serde(default)]
+    pub transactions: Vec<QueuedTransaction>,
+}
+
+impl TimelockQueue {
+    pub fn load(path: &str) -> DeployResult<TimelockQueue> {
+
+        if !std::path::Path::new(path).exists() {
+            return Ok(TimelockQueue { path: path.to_string(), ..TimelockQueue::default() });
+        }
+
+        let file = std::fs::File::open(path).map_err(|e| DeployError::config(format!("failed to open {}: {}", path, e)))?;
+        let reader = std::io::BufReader::new(file);
+        let mut queue: TimelockQueue =
+            serde_json::from_reader(reader).map_err(|e| DeployError::config(format!("{} is malformed: {}", path, e)))?;
+        queue.path = path.to_string();
+        Ok(queue)
+    }
+    pub fn save(&self) -> DeployResult<()> {
+        let data: Vec<u8> = serde_json::to_vec_pretty(self).map_err(|e| DeployError::config(format!("failed to serialize the timelock queue: {}", e)))?;
+        write_atomically(&self.path, &data, DeployError::config)
+    }
+    pub fn get(&self, hash: H256) -> DeployResult<&QueuedTransaction> {
+        self.transactions.iter()
+            .find(|tx| tx.hash == hash)
+            .ok_or_else(|| DeployError::config(format!("{:?} is not in the timelock queue {}", hash, self.path)))
+    }
+    fn set_status(&mut self, hash: H256, status: QueueStatus) -> DeployResult<()> {
+        if let Some(tx) = self.transactions.iter_mut().find(|tx| tx.hash == hash) {
+            tx.status = status;
+        }
+        self.save()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Readiness {
+    Waiting(u64),
+    Ready,
+    Expired,
+}
+
+// Saturates, as an ETA loaded from a hand-edited queue can be any uint.
+fn readiness_at(eta: U256, now: U256, grace_period: U256) -> Readiness {
+    if now < eta {
+        Readiness::Waiting((eta - now).min(U256::from(u64::MAX)).as_u64())
+    } else if now > eta.saturating_add(grace_period) {
+        Readiness::Expired
+    } else {
+        Readiness::Ready
+    }
+}
+
+pub struct TimelockClient<'a> {
+    env: &'a NeonswapEnvironment,
+    contract: Contract<web3::transports::Http>,
+}
+
+impl<'a> TimelockClient<'a> {
+    pub fn new(env: &'a NeonswapEnvironment, artifacts: &ArtifactRegistry) -> DeployResult<TimelockClient<'a>> {
+        let contract: Contract<web3::transports::Http> = artifacts.contract(&env.web3, "timelock", env.contract_address("timelock")?)?;
+        Ok(TimelockClient { env, contract })
+    }
+    async fn query<R: Detokenize>(&self, method: &str, params: impl Tokenize) -> DeployResult<R> {
+        self.env.query(&self.contract, "timelock", method, params).await
+    }
+    async fn send(&self, role: &str, operation: &str, method: &str, params: impl Tokenize) -> DeployResult<()> {
+        self.env.send_call(role, operation, &self.contract, method, params)
+            .await
+            .map(|_| ())
+            .map_err(|e| e.for_contract("timelock"))
+    }
+    pub async fn delay(&self) -> DeployResult<U256> {
+        self.query("delay", ()).await
+    }
+    pub async fn grace_period(&self) -> DeployResult<U256> {
+        self.query("GRACE_PERIOD", ()).await
+    }
+    pub async fn admin(&self) -> DeployResult<(Address, Address)> {
+        let admin: Address = self.query("admin", ()).await?;
+        let pending_admin: Address = self.query("pendingAdmin", ()).await?;
+        Ok((admin, pending_admin))
+    }
+    // Queueing, executing and canceling are admin only, as is the first handover. Once
+    // GovernorAlpha is the admin, these happen through proposals instead.
+    async fn check_admin(&self, role: &str) -> DeployResult<()> {
+        let admin: Address = self.query("admin", ()).await?;
+        if admin == self.env.signers.get(role)?.address {
+            return Ok(());
+        }
+        let hint: &str = if self.env.address_book.address("governor_alpha") == Some(admin) { ", propose the action through governance instead" } else { "" };
+        Err(DeployError::config(format!("{} is not the Timelock admin, {} is{}", role, admin.as_eip55(), hint)).for_contract("timelock"))
+    }
+    pub async fn is_queued(&self, hash: H256) -> DeployResult<bool> {
+        self.query("queuedTransactions", hash).await
+    }
+    pub async fn readiness(&self, tx: &QueuedTransaction) -> DeployResult<Readiness> {
+        let now: U256 = self.env.chain_time().await?;
+        let grace_period: U256 = self.grace_period().await?;
+        Ok(readiness_at(tx.eta, now, grace_period))
+    }
+    // Queues the action with the earliest ETA the delay allows unless one is given.
+    // Only the Timelock admin can queue.
+    pub async fn queue(&self, role: &str, queue: &mut TimelockQueue, action: &EncodedAction, eta: Option<U256>) -> DeployResult<H256> {
+
+        self.check_admin(role).await?;
+        let earliest: U256 = self.env.chain_time().await? + self.delay().await?;
+        let eta: U256 = eta.unwrap_or(earliest + U256::from(ETA_MARGIN_SECS));
+        if eta < earliest {
+            return Err(DeployError::config(format!("eta {} is before the earliest allowed eta {}", eta, earliest)));
+        }
+        if eta >= U256::from(MAX_SECONDS_TIMESTAMP) {
+            return Err(DeployError::config(format!("eta {} looks like milliseconds, it must be a unix timestamp in seconds", eta)));
+        }
+
+        let tx = QueuedTransaction {
+            hash: transaction_hash(action.target, action.value, &action.signature, &action.calldata, eta),
+            target: action.target,
+            value: action.value,
+            signature: action.signature.clone(),
+            data: Bytes(action.calldata.clone()),
+            eta,
+            status: QueueStatus::Queued,
+        };
+        self.send(role, "timelock_queue", "queueTransaction", &tx.params()[..]).await?;
+        if !self.is_queued(tx.hash).await? {
+            return Err(DeployError::rpc(format!("the Timelock does not report {:?} as queued, its hash differs from ours", tx.hash)).for_contract("timelock"));
+        }
+
+        let hash: H256 = tx.hash;
+        queue.transactions.push(tx);
+        queue.save()?;
+        Ok(hash)
+    }
+    pub async fn execute(&self, role: &str, queue: &mut TimelockQueue, hash: H256) -> DeployResult<()> {
+
+        self.check_admin(role).await?;
+        let tx: QueuedTransaction = queue.get(hash)?.clone();
+        match self.readiness(&tx).await? {
+            Readiness::Ready => {},
+            Readiness::Waiting(secs) => return Err(DeployError::config(format!("{:?} is not ready for another {} seconds", hash, secs))),
+            Readiness::Expired => return Err(DeployError::config(format!("{:?} is past its grace period and can only be canceled", hash))),
+        }
+        self.env.send_payable(role, "timelock_execute", &self.contract, "executeTransaction", &tx.params()[..], tx.value)
+            .await
+            .map_err(|e| e.for_contract("timelock"))?;
+        queue.set_status(hash, QueueStatus::Executed)
+    }
+    pub async fn cancel(&self, role: &str, queue: &mut TimelockQueue, hash: H256) -> DeployResult<()> {
+        let tx: QueuedTransaction = queue.get(hash)?.clone();
+        self.check_admin(role).await?;
+        self.send(role, "timelock_cancel", "cancelTransaction", &tx.params()[..]).await?;
+        queue.set_status(hash, QueueStatus::Canceled)
+    }
+    // Runs every queued transaction whose ETA has passed and whose grace period has not.
+    pub async fn execute_ready(&self, role: &str, queue: &mut TimelockQueue) -> DeployResult<usize> {
+        let mut executed: usize = 0;
+        let candidates: Vec<QueuedTransaction> = queue.transactions.iter().filter(|tx| tx.status == QueueStatus::Queued).cloned().collect();
+        for tx in candidates.iter() {
+            if self.readiness(tx).await? == Readiness::Ready {
+                self.execute(role, queue, tx.hash).await?;
+                println!("Executed {:?} ({})", tx.hash, tx.signature);
+                executed += 1;
+            }
+        }
+        Ok(executed)
+    }
+    // The first handover can come from the admin; later ones have to be queued
+    // through the Timelock itself as a call to its own setPendingAdmin.
+    pub async fn set_pending_admin(&self, role: &str, pending_admin: Address) -> DeployResult<()> {
+        self.check_admin(role).await?;
+        self.send(role, "timelock_set_pending_admin", "setPendingAdmin", pending_admin).await
+    }
+    pub async fn accept_admin(&self, role: &str) -> DeployResult<()> {
+        self.send(role, "timelock_accept_admin", "acceptAdmin", ()).await
+    }
+    pub async fn report(&self, queue: &TimelockQueue) -> DeployResult<()> {
+
+        let (admin, pending_admin) = self.admin().await?;
+        let grace_period: U256 = self.grace_period().await?;
+        println!("Timelock admin {}, pending admin {}", admin.as_eip55(), pending_admin.as_eip55());
+        println!("Delay {} seconds, grace period {} seconds, chain time {}", self.delay().await?, grace_period, self.env.chain_time().await?);
+
+        for tx in queue.transactions.iter() {
+            let state: String =
+                match tx.status {
+                    QueueStatus::Executed => "executed".to_string(),
+                    QueueStatus::Canceled => "canceled".to_string(),
+                    QueueStatus::Queued if !self.is_queued(tx.hash).await? => "no longer queued on chain".to_string(),
+                    QueueStatus::Queued => {
+                        match self.readiness(tx).await? {
+                            Readiness::Waiting(secs) => format!("ready in {} seconds", secs),
+                            Readiness::Ready => "ready".to_string(),
+                            Readiness::Expired => "expired".to_string(),
+                        }
+                    },
+                };
+            println!("{:?}", tx.hash);
+            println!("    {} {} value {}", tx.target.as_eip55(), tx.signature, tx.value);
+            println!("    eta {}, expires {}: {}", tx.eta, tx.eta.saturating_add(grace_period), state);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashes_like_queue_transaction() {
+        // keccak256(abi.encode(target, value, signature, data, eta)) computed outside this crate
+        let deployer: Address = Address::repeat_byte(0xde);
+        let data: Vec<u8> = web3::ethabi::encode(&[Token::Address(deployer)]);
+        let hash: H256 = transaction_hash(Address::repeat_byte(0xfa), U256::zero(), "setFeeTo(address)", &data, U256::from(1_700_000_000u64));
+        assert_eq!(hash, "19265e9b964816fde9f7d8eae0d6d0e8901a2f814a40545caa9448e00b99c33a".parse().unwrap());
+    }
+
+    #[test]
+    fn tells_waiting_ready_and_expired_apart() {
+        let eta: U256 = U256::from(1000);
+        let grace_period: U256 = U256::from(100);
+        assert_eq!(readiness_at(eta, U256::from(400), grace_period), Readiness::Waiting(600));
+        assert_eq!(readiness_at(eta, eta, grace_period), Readiness::Ready);
+        assert_eq!(readiness_at(eta, U256::from(1100), grace_period), Readiness::Ready);
+        assert_eq!(readiness_at(eta, U256::from(1101), grace_period), Readiness::Expired);
+    }
+
+    #[test]
+    fn saturates_an_absurd_eta() {
+        assert_eq!(readiness_at(U256::MAX, U256::from(1000), U256::from(100)), Readiness::Waiting(u64::MAX));
+        assert_eq!(readiness_at(U256::from(1000), U256::MAX, U256::MAX), Readiness::Ready);
+    }
+}
@@ -0,0 +1,264 @@
+use std::collections::BTreeMap;
+use serde::{ Serialize };
+
+use web3::types::{ Address, BlockId, BlockNumber, FilterBuilder, Log, H256, U256, U64 };
+use web3::contract::Contract;
+use web3::contract::tokens::{ Detokenize, Tokenize };
+
+use web3_tools::AsEip55;
+
+use crate::artifact::{ Artifact, ArtifactRegistry };
+use crate::liquidity::NeonswapEnvironment;
+use crate::error::{ DeployError, DeployResult };
+
+#[derive(Clone, Copy, Debug)]
+#[derive(Serialize)]
+pub struct Checkpoint {
+    pub from_block: u32,
+    pub votes: U256,
+}
+
+#[derive(Clone, Debug)]
+#[derive(Serialize)]
+pub struct HolderPower {
+    pub address: Address,
+    // role or contract name when the account is one of ours
+    pub label: Option<String>,
+    pub balance: U256,
+    pub delegate: Address,
+    pub votes: U256,
+}
+
+#[derive(Clone, Debug)]
+#[derive(Serialize)]
+pub struct VotingSnapshot {
+    pub block: U64,
+    pub uni: Address,
+    pub total_votes: U256,
+    pub holders: Vec<HolderPower>,
+}
+
+// What Uni.mint checks, read in one go.
+pub struct MintSchedule {
+    pub minter: Address,
+    pub minting_allowed_after: U256,
+    pub minimum_time_between_mints: U256,
+    // percent of the total supply one mint may add
+    pub mint_cap: U256,
+    pub total_supply: U256,
+    pub now: U256,
+}
+
+impl MintSchedule {
+    pub fn max_mint(&self) -> U256 {
+        self.total_supply * self.mint_cap / 100
+    }
+    // When the next mint can happen, and when the one after it could if minted then.
+    pub fn next_mints(&self) -> (U256, U256) {
+        let next: U256 = std::cmp::max(self.minting_allowed_after, self.now);
+        (next, next + self.minimum_time_between_mints)
+    }
+    pub fn report(&self) {
+        let (next, following) = self.next_mints();
+        println!("Minter {}", self.minter.as_eip55());
+        println!("Total supply {}, one mint may add up to {} ({}%)", self.total_supply, self.max_mint(), self.mint_cap);
+        if self.now >= self.minting_allowed_after {
+            println!("Minting is allowed now (chain time {}), the mint after that from {}", self.now, following);
+        } else {
+            println!("Minting is allowed from {}, in {} seconds, the mint after that from {}", next, self.minting_allowed_after - self.now, following);
+        }
+    }
+}
+
+fn topic_address(topic: &H256) -> Address {
+    Address::from_slice(&topic.as_bytes()[12..])
+}
+
+// Typed access to the UNI token's delegation and vote checkpoints.
+pub struct UniClient<'a> {
+    env: &'a NeonswapEnvironment,
+    artifact: &'a Artifact,
+    contract: Contract<web3::transports::Http>,
+}
+
+impl<'a> UniClient<'a> {
+    pub fn new(env: &'a NeonswapEnvironment, artifacts: &'a ArtifactRegistry) -> DeployResult<UniClient<'a>> {
+        let artifact: &Artifact = artifacts.get("uni")?;
+        let contract: Contract<web3::transports::Http> = artifact.at(&env.web3, env.contract_address("uni")?);
+        Ok(UniClient { env, artifact, contract })
+    }
+    async fn query<R: Detokenize>(&self, method: &str, params: impl Tokenize, block: Option<BlockId>) -> DeployResult<R> {
+        self.env.query_at(&self.contract, "uni", method, params, block).await
+    }
+    pub async fn delegate(&self, role: &str, delegatee: Address) -> DeployResult<()> {
+        self.env.send_call(role, "uni_delegate", &self.contract, "delegate", delegatee)
+            .await
+            .map(|_| ())
+            .map_err(|e| e.for_contract("uni"))
+    }
+    pub async fn mint_schedule(&self) -> DeployResult<MintSchedule> {
+        Ok(MintSchedule {
+            minter: self.query("minter", (), None).await?,
+            minting_allowed_after: self.query("mintingAllowedAfter", (), None).await?,
+            minimum_time_between_mints: self.query("minimumTimeBetweenMints", (), None).await?,
+            mint_cap: self.query::<u8>("mintCap", (), None).await?.into(),
+            total_supply: self.query("totalSupply", (), None).await?,
+            now: self.env.chain_time().await?,
+        })
+    }
+    // Checks what Uni.mint would reject before sending. Once the Timelock is the minter,
+    // minting has to go through a governance proposal calling uni mint.
+    pub async fn mint(&self, role: &str, to: Address, amount: U256) -> DeployResult<MintSchedule> {
+
+        let schedule: MintSchedule = self.mint_schedule().await?;
+        let sender: Address = self.env.signers.get(role)?.address;
+        if schedule.minter != sender {
+            let timelock: Option<Address> = self.env.address_book.address("timelock");
+            let hint: &str = if timelock == Some(schedule.minter) { ", propose the mint through governance instead" } else { "" };
+            return Err(DeployError::config(format!("{} is not the minter, {} is{}", role, schedule.minter.as_eip55(), hint)).for_contract("uni"));
+        }
+        if schedule.now < schedule.minting_allowed_after {
+            return Err(DeployError::config(format!("minting is not allowed for another {} seconds", schedule.minting_allowed_after - schedule.now)).for_contract("uni"));
+        }
+        if to.is_zero() {
+            return Err(DeployError::config("cannot mint to the zero address").for_contract("uni"));
+        }
+        if amount > schedule.max_mint() {
+            return Err(DeployError::config(format!("{} exceeds the mint cap of {}", amount, schedule.max_mint())).for_contract("uni"));
+        }
+
+        self.env.send_call(role, "uni_mint", &self.contract, "mint", (to, amount))
+            .await
+            .map_err(|e| e.for_contract("uni"))?;
+        self.mint_schedule().await
+    }
+    pub async fn delegates(&self, account: Address) -> DeployResult<Address> {
+        self.query("delegates", account, None).await
+    }
+    pub async fn current_votes(&self, account: Address) -> DeployResult<U256> {
+        self.query("getCurrentVotes", account, None).await
+    }
+    pub async fn checkpoints(&self, account: Address) -> DeployResult<Vec<Checkpoint>> {
+        let count: u32 = self.query("numCheckpoints", account, None).await?;
+        let mut checkpoints: Vec<Checkpoint> = Vec::new();
+        for index in 0..count {
+            let (from_block, votes): (u32, U256) = self.query("checkpoints", (account, index), None).await?;
+            checkpoints.push(Checkpoint { from_block, votes });
+        }
+        Ok(checkpoints)
+    }
+    // Every account that ever received UNI or had votes delegated to it, from the token's logs.
+    async fn holders_from_logs(&self, to_block: U64) -> DeployResult<Vec<Address>> {
+
+        let from_block: U64 = self.env.address_book.contracts.get("uni").and_then(|record| record.block).unwrap_or_default();
+        let mut holders: Vec<Address> = Vec::new();
+        for (event, topic_index) in [("Transfer", 2usize), ("DelegateVotesChanged", 1usize)] {
+            let signature: H256 =
+                self.artifact.abi.event(event)
+                    .map(|e| e.signature())
+                    .map_err(|_| DeployError::artifact(format!("uni has no event {}", event)))?;
+            let filter =
+                FilterBuilder::default()
+                    .address(vec![self.contract.address()])
+                    .topics(Some(vec![signature]), None, None, None)
+                    .from_block(BlockNumber::Number(from_block))
+                    .to_block(BlockNumber::Number(to_block))
+                    .build();
+            let logs: Vec<Log> = self.env.web3.eth().logs(filter).await.map_err(|e| DeployError::rpc(e).for_contract("uni"))?;
+            holders.extend(logs.iter().filter_map(|log| log.topics.get(topic_index)).map(topic_address));
+        }
+        Ok(holders)
+    }
+    // Balance, delegate and votes of every known account as of `block`. GovernorAlpha
+    // counts votes with getPriorVotes, so the block has to be mined already.
+    pub async fn snapshot(&self, block: U64) -> DeployResult<VotingSnapshot> {
+
+        let latest: U64 = self.env.web3.eth().block_number().await.map_err(DeployError::rpc)?;
+        if block >= latest {
+            return Err(DeployError::config(format!("block {} is not mined yet, the latest block is {}", block, latest)));
+        }
+
+        let mut labels: BTreeMap<Address,Option<String>> = BTreeMap::new();
+        for signer in self.env.signers.iter() {
+            labels.insert(signer.address, Some(signer.role.clone()));
+        }
+        for (name, record) in self.env.address_book.contracts.iter() {
+            labels.entry(record.address).or_insert_with(|| Some(name.clone()));
+        }
+        for holder in self.holders_from_logs(block).await? {
+            labels.entry(holder).or_insert(None);
+        }
+        labels.remove(&Address::zero());
+
+        let at: Option<BlockId> = Some(BlockId::Number(BlockNumber::Number(block)));
+        let mut holders: Vec<HolderPower> = Vec::new();
+        for (address, label) in labels.into_iter() {
+            let balance: U256 = self.query("balanceOf", address, at).await?;
+            let votes: U256 = self.query("getPriorVotes", (address, U256::from(block.as_u64())), None).await?;
+            if balance.is_zero() && votes.is_zero() {
+                continue;
+            }
+            let delegate: Address = self.query("delegates", address, at).await?;
+            holders.push(HolderPower { address, label, balance, delegate, votes });
+        }
+
+        Ok(VotingSnapshot {
+            block,
+            uni: self.contract.address(),
+            total_votes: holders.iter().fold(U256::zero(), |total, holder| total + holder.votes),
+            holders,
+        })
+    }
+}
+
+impl VotingSnapshot {
+    pub fn report(&self) {
+        println!("Voting power at block {}, {} votes delegated in total", self.block, self.total_votes);
+        println!("{:<44} {:<20} {:>28} {:>28}  {}", "Account", "Label", "Balance", "Votes", "Delegate");
+        for holder in self.holders.iter() {
+            println!("{:<44} {:<20} {:>28} {:>28}  {}",
+                holder.address.as_eip55(), holder.label.as_deref().unwrap_or("-"), holder.balance, holder.votes, holder.delegate.as_eip55());
+        }
+    }
+    pub fn export(&self, path: &str) -> DeployResult<()> {
+        let data: Vec<u8> = serde_json::to_vec_pretty(self).map_err(|e| DeployError::config(format!("failed to serialize the snapshot: {}", e)))?;
+        std::fs::write(path, data).map_err(|e| DeployError::config(format!("failed to write {}: {}", path, e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schedule(total_supply: U256, minting_allowed_after: u64, now: u64) -> MintSchedule {
+        MintSchedule {
+            minter: Address::repeat_byte(0x11),
+            minting_allowed_after: U256::from(minting_allowed_after),
+            minimum_time_between_mints: U256::from(365 * 24 * 3600),
+            mint_cap: U256::from(2),
+            total_supply,
+            now: U256::from(now),
+        }
+    }
+
+    #[test]
+    fn caps_a_mint_at_two_percent_of_the_supply() {
+        let supply: U256 = U256::from(1_000_000_000u64) * U256::exp10(18);
+        assert_eq!(schedule(supply, 0, 0).max_mint(), U256::from(20_000_000u64) * U256::exp10(18));
+        // Uni.mint divides after multiplying, so the cap rounds down
+        assert_eq!(schedule(U256::from(149), 0, 0).max_mint(), U256::from(2));
+        assert_eq!(schedule(U256::from(49), 0, 0).max_mint(), U256::zero());
+    }
+
+    #[test]
+    fn schedules_the_next_mints_around_minting_allowed_after() {
+        let year: U256 = U256::from(365 * 24 * 3600);
+        let allowed: u64 = 1_700_000_000;
+        // before: the next mint waits for mintingAllowedAfter
+        assert_eq!(schedule(U256::one(), allowed, allowed - 1).next_mints(), (U256::from(allowed), U256::from(allowed) + year));
+        // exactly at it Uni.mint already passes
+        assert_eq!(schedule(U256::one(), allowed, allowed).next_mints(), (U256::from(allowed), U256::from(allowed) + year));
+        // after: the interval counts from the mint itself
+        assert_eq!(schedule(U256::one(), allowed, allowed + 10).next_mints(), (U256::from(allowed + 10), U256::from(allowed + 10) + year));
+    }
+}
@@ -0,0 +1,373 @@
+use std::fmt;
+
+use web3::types::{ Address, H256, Log, TransactionReceipt, U256 };
+use web3::ethabi::Token;
+use web3::contract::{ Contract, Options };
+use web3::contract::tokens::{ Detokenize, Tokenize };
+
+use web3_tools::AsEip55;
+
+use crate::artifact::{ Artifact, ArtifactRegistry };
+use crate::config::MAX_SECONDS_TIMESTAMP;
+use crate::liquidity::NeonswapEnvironment;
+use crate::error::{ DeployError, DeployResult };
+
+// Every V1 exchange event indexes all three of its parameters, so they are read from the topics.
+#[derive(Clone, Copy, Debug)]
+pub enum V1Event {
+    TokenPurchase { buyer: Address, eth_sold: U256, tokens_bought: U256 },
+    EthPurchase { buyer: Address, tokens_sold: U256, eth_bought: U256 },
+    AddLiquidity { provider: Address, eth_amount: U256, token_amount: U256 },
+    RemoveLiquidity { provider: Address, eth_amount: U256, token_amount: U256 },
+}
+
+impl fmt::Display for V1Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            V1Event::TokenPurchase { buyer, eth_sold, tokens_bought } =>
+                write!(f, "TokenPurchase: {} sold {} wei ETH for {} tokens", buyer.as_eip55(), eth_sold, tokens_bought),
+            V1Event::EthPurchase { buyer, tokens_sold, eth_bought } =>
+                write!(f, "EthPurchase: {} sold {} tokens for {} wei ETH", buyer.as_eip55(), tokens_sold, eth_bought),
+            V1Event::AddLiquidity { provider, eth_amount, token_amount } =>
+                write!(f, "AddLiquidity: {} added {} wei ETH and {} tokens", provider.as_eip55(), eth_amount, token_amount),
+            V1Event::RemoveLiquidity { provider, eth_amount, token_amount } =>
+                write!(f, "RemoveLiquidity: {} removed {} wei ETH and {} tokens", provider.as_eip55(), eth_amount, token_amount),
+        }
+    }
+}
+
+pub struct V1Receipt {
+    pub transaction_hash: H256,
+    pub events: Vec<V1Event>,
+}
+
+impl V1Receipt {
+    pub fn report(&self) {
+        println!("{:?}", self.transaction_hash);
+        for event in self.events.iter() {
+            println!("    {}", event);
+        }
+    }
+}
+
+fn topic_address(topic: &H256) -> Address {
+    Address::from_slice(&topic.as_bytes()[12..])
+}
+
+fn topic_uint(topic: &H256) -> U256 {
+    U256::from_big_endian(topic.as_bytes())
+}
+
+// The exchange asserts every amount and limit is positive.
+fn check_amount(name: &str, amount: U256) -> DeployResult<()> {
+    if amount.is_zero() {
+        return Err(DeployError::config(format!("{} must be greater than zero", name)).for_contract("uniswap_v1exchange"));
+    }
+    Ok(())
+}
+
+// Typed access to one token's Uniswap V1 exchange.
+pub struct V1Exchange<'a> {
+    env: &'a NeonswapEnvironment,
+    artifact: &'a Artifact,
+    factory: Contract<web3::transports::Http>,
+    contract: Contract<web3::transports::Http>,
+    token: Contract<web3::transports::Http>,
+}
+
+impl<'a> V1Exchange<'a> {
+    pub async fn for_token(env: &'a NeonswapEnvironment, artifacts: &'a ArtifactRegistry, token: Address) -> DeployResult<V1Exchange<'a>> {
+        let factory: Contract<web3::transports::Http> = artifacts.contract(&env.web3, "uniswap_v1factory", env.contract_address("uniswap_v1factory")?)?;
+        let exchange: Address =
+            factory.query("getExchange", token, env.signers.deployer().address, Options::default(), None)
+                .await
+                .map_err(|e| DeployError::rpc(e).for_contract("uniswap_v1factory"))?;
+        if exchange.is_zero() {
+            return Err(DeployError::config(format!("there is no V1 exchange for {}", token.as_eip55())).for_contract("uniswap_v1factory"));
+        }
+        let artifact: &Artifact = artifacts.get("uniswap_v1exchange")?;
+        Ok(V1Exchange {
+            env,
+            artifact,
+            factory,
+            contract: artifact.at(&env.web3, exchange),
+            token: artifacts.contract(&env.web3, "erc20", token)?,
+        })
+    }
+    // An exchange by its own address, the token is whatever it was set up for.
+    pub async fn at(env: &'a NeonswapEnvironment, artifacts: &'a ArtifactRegistry, exchange: Address) -> DeployResult<V1Exchange<'a>> {
+        let contract: Contract<web3::transports::Http> = artifacts.contract(&env.web3, "uniswap_v1exchange", exchange)?;
+        let token: Address =
+            contract.query("tokenAddress", (), env.signers.deployer().address, Options::default(), None)
+                .await
+                .map_err(|e| DeployError::rpc(e).for_contract("uniswap_v1exchange"))?;
+        if token.is_zero() {
+            return Err(DeployError::config(format!("{} is not a V1 exchange that has been set up", exchange.as_eip55())).for_contract("uniswap_v1exchange"));
+        }
+        V1Exchange::for_token(env, artifacts, token).await
+    }
+    pub fn address(&self) -> Address {
+        self.contract.address()
+    }
+    pub fn token_address(&self) -> Address {
+        self.token.address()
+    }
+    async fn query<R: Detokenize>(&self, method: &str, params: impl Tokenize) -> DeployResult<R> {
+        self.env.query(&self.contract, "uniswap_v1exchange", method, params).await
+    }
+    // A deadline `secs` from now by the chain's clock, which is what the exchange compares it to.
+    pub async fn deadline(&self, secs: u64) -> DeployResult<U256> {
+        Ok(self.env.chain_time().await? + U256::from(secs))
+    }
+    async fn check_deadline(&self, deadline: U256) -> DeployResult<()> {
+        if deadline >= U256::from(MAX_SECONDS_TIMESTAMP) {
+            return Err(DeployError::config(format!("deadline {} looks like milliseconds, it must be a unix timestamp in seconds", deadline)).for_contract("uniswap_v1exchange"));
+        }
+        let now: U256 = self.env.chain_time().await?;
+        if deadline <= now {
+            return Err(DeployError::config(format!("deadline {} is not after the chain time {}", deadline, now)).for_contract("uniswap_v1exchange"));
+        }
+        Ok(())
+    }
+    fn check_recipient(&self, recipient: Option<Address>) -> DeployResult<()> {
+        match recipient {
+            Some(recipient) if recipient.is_zero() || recipient == self.address() =>
+                Err(DeployError::config(format!("{} cannot receive the output of a swap", recipient.as_eip55())).for_contract("uniswap_v1exchange")),
+            _ => Ok(()),
+        }
+    }
+    // tokenToToken goes through the output token's exchange, which has to exist and be another one.
+    async fn check_output_token(&self, token: Address) -> DeployResult<()> {
+        let exchange: Address =
+            self.factory.query("getExchange", token, self.env.signers.deployer().address, Options::default(), None)
+                .await
+                .map_err(|e| DeployError::rpc(e).for_contract("uniswap_v1factory"))?;
+        self.check_output_exchange(exchange).map_err(|_| DeployError::config(format!("there is no other V1 exchange for {}", token.as_eip55())).for_contract("uniswap_v1factory"))
+    }
+    fn check_output_exchange(&self, exchange: Address) -> DeployResult<()> {
+        if exchange.is_zero() || exchange == self.address() {
+            return Err(DeployError::config(format!("{} is not another V1 exchange", exchange.as_eip55())).for_contract("uniswap_v1exchange"));
+        }
+        Ok(())
+    }
+    fn event_signature(&self, name: &str) -> DeployResult<H256> {
+        self.artifact.abi.event(name)
+            .map(|e| e.signature())
+            .map_err(|_| DeployError::artifact(format!("uniswap_v1exchange has no event {}", name)))
+    }
+    // The exchange events this exchange emitted in the receipt, in log order.
+    pub fn decode_events(&self, receipt: &TransactionReceipt) -> DeployResult<Vec<V1Event>> {
+
+        let token_purchase: H256 = self.event_signature("TokenPurchase")?;
+        let eth_purchase: H256 = self.event_signature("EthPurchase")?;
+        let add_liquidity: H256 = self.event_signature("AddLiquidity")?;
+        let remove_liquidity: H256 = self.event_signature("RemoveLiquidity")?;
+
+        let mut events: Vec<V1Event> = Vec::new();
+        for log in receipt.logs.iter().filter(|log: &&Log| log.address == self.address() && log.topics.len() == 4) {
+            let (account, first, second) = (topic_address(&log.topics[1]), topic_uint(&log.topics[2]), topic_uint(&log.topics[3]));
+            let topic: H256 = log.topics[0];
+            if topic == token_purchase {
+                events.push(V1Event::TokenPurchase { buyer: account, eth_sold: first, tokens_bought: second });
+            } else if topic == eth_purchase {
+                events.push(V1Event::EthPurchase { buyer: account, tokens_sold: first, eth_bought: second });
+            } else if topic == add_liquidity {
+                events.push(V1Event::AddLiquidity { provider: account, eth_amount: first, token_amount: second });
+            } else if topic == remove_liquidity {
+                events.push(V1Event::RemoveLiquidity { provider: account, eth_amount: first, token_amount: second });
+            }
+        }
+        Ok(events)
+    }
+    async fn trade(&self, role: &str, operation: &str, method: &str, params: Vec<Token>, value: Option<U256>) -> DeployResult<V1Receipt> {
+        let receipt: TransactionReceipt =
+            match value {
+                Some(value) => self.env.send_payable(role, operation, &self.contract, method, &params[..], value).await,
+                None => self.env.send_call(role, operation, &self.contract, method, &params[..]).await,
+            }
+            .map_err(|e| e.for_contract("uniswap_v1exchange"))?;
+        Ok(V1Receipt { transaction_hash: receipt.transaction_hash, events: self.decode_events(&receipt)? })
+    }
+
+    pub async fn token_balance(&self, owner: Address) -> DeployResult<U256> {
+        self.token.query("balanceOf", owner, self.env.signers.deployer().address, Options::default(), None)
+            .await
+            .map_err(|e| DeployError::rpc(e).for_contract("erc20"))
+    }
+    // The exchange's ETH and token reserves.
+    pub async fn reserves(&self) -> DeployResult<(U256, U256)> {
+        let eth: U256 = self.env.web3.eth().balance(self.address(), None).await.map_err(DeployError::rpc)?;
+        Ok((eth, self.token_balance(self.address()).await?))
+    }
+    pub async fn total_supply(&self) -> DeployResult<U256> {
+        self.query("totalSupply", ()).await
+    }
+    pub async fn liquidity_balance(&self, owner: Address) -> DeployResult<U256> {
+        self.query("balanceOf", owner).await
+    }
+    // Liquidity minted and tokens taken for `eth_amount` at the current reserves, as
+    // addLiquidity computes them. Into an empty exchange the caller sets the price.
+    pub async fn liquidity_quote(&self, eth_amount: U256) -> DeployResult<Option<(U256, U256)>> {
+        let total_supply: U256 = self.total_supply().await?;
+        if total_supply.is_zero() {
+            return Ok(None);
+        }
+        let (eth_reserve, token_reserve) = self.reserves().await?;
+        Ok(Some((eth_amount * total_supply / eth_reserve, eth_amount * token_reserve / eth_reserve + 1)))
+    }
+    pub async fn approve_token(&self, role: &str, amount: U256) -> DeployResult<()> {
+        self.env.send_call(role, "v1_approve", &self.token, "approve", (self.address(), amount))
+            .await
+            .map(|_| ())
+            .map_err(|e| e.for_contract("erc20"))
+    }
+
+    pub async fn get_eth_to_token_input_price(&self, eth_sold: U256) -> DeployResult<U256> {
+        check_amount("eth_sold", eth_sold)?;
+        self.query("getEthToTokenInputPrice", eth_sold).await
+    }
+    pub async fn get_eth_to_token_output_price(&self, tokens_bought: U256) -> DeployResult<U256> {
+        check_amount("tokens_bought", tokens_bought)?;
+        self.query("getEthToTokenOutputPrice", tokens_bought).await
+    }
+    pub async fn get_token_to_eth_input_price(&self, tokens_sold: U256) -> DeployResult<U256> {
+        check_amount("tokens_sold", tokens_sold)?;
+        self.query("getTokenToEthInputPrice", tokens_sold).await
+    }
+    pub async fn get_token_to_eth_output_price(&self, eth_bought: U256) -> DeployResult<U256> {
+        check_amount("eth_bought", eth_bought)?;
+        self.query("getTokenToEthOutputPrice", eth_bought).await
+    }
+
+    // The first deposit sets the price and needs at least 1 gwei, min_liquidity only
+    // applies once the exchange holds liquidity.
+    pub async fn add_liquidity(&self, role: &str, eth_amount: U256, min_liquidity: U256, max_tokens: U256, deadline: U256) -> DeployResult<V1Receipt> {
+        check_amount("eth_amount", eth_amount)?;
+        check_amount("max_tokens", max_tokens)?;
+        if self.total_supply().await?.is_zero() {
+            check_amount("eth_amount in gwei", eth_amount / U256::exp10(9))?;
+        } else {
+            check_amount("min_liquidity", min_liquidity)?;
+        }
+        self.check_deadline(deadline).await?;
+        let params: Vec<Token> = vec![Token::Uint(min_liquidity), Token::Uint(max_tokens), Token::Uint(deadline)];
+        self.trade(role, "v1_add_liquidity", "addLiquidity", params, Some(eth_amount)).await
+    }
+    pub async fn remove_liquidity(&self, role: &str, amount: U256, min_eth: U256, min_tokens: U256, deadline: U256) -> DeployResult<V1Receipt> {
+        check_amount("amount", amount)?;
+        check_amount("min_eth", min_eth)?;
+        check_amount("min_tokens", min_tokens)?;
+        self.check_deadline(deadline).await?;
+        let params: Vec<Token> = vec![Token::Uint(amount), Token::Uint(min_eth), Token::Uint(min_tokens), Token::Uint(deadline)];
+        self.trade(role, "v1_remove_liquidity", "removeLiquidity", params, None).await
+    }
+
+    // The swaps below use the Transfer variant when a recipient is given and the Swap
+    // variant, paying the sender, otherwise.
+
+    pub async fn eth_to_token_input(&self, role: &str, eth_sold: U256, min_tokens: U256, deadline: U256, recipient: Option<Address>) -> DeployResult<V1Receipt> {
+        check_amount("eth_sold", eth_sold)?;
+        check_amount("min_tokens", min_tokens)?;
+        self.check_deadline(deadline).await?;
+        self.check_recipient(recipient)?;
+        let mut params: Vec<Token> = vec![Token::Uint(min_tokens), Token::Uint(deadline)];
+        let method: &str =
+            match recipient {
+                Some(recipient) => { params.push(Token::Address(recipient)); "ethToTokenTransferInput" },
+                None => "ethToTokenSwapInput",
+            };
+        self.trade(role, "v1_eth_to_token_input", method, params, Some(eth_sold)).await
+    }
+    // Sends max_eth, the exchange refunds what the tokens did not cost.
+    pub async fn eth_to_token_output(&self, role: &str, tokens_bought: U256, max_eth: U256, deadline: U256, recipient: Option<Address>) -> DeployResult<V1Receipt> {
+        check_amount("tokens_bought", tokens_bought)?;
+        check_amount("max_eth", max_eth)?;
+        self.check_deadline(deadline).await?;
+        self.check_recipient(recipient)?;
+        let mut params: Vec<Token> = vec![Token::Uint(tokens_bought), Token::Uint(deadline)];
+        let method: &str =
+            match recipient {
+                Some(recipient) => { params.push(Token::Address(recipient)); "ethToTokenTransferOutput" },
+                None => "ethToTokenSwapOutput",
+            };
+        self.trade(role, "v1_eth_to_token_output", method, params, Some(max_eth)).await
+    }
+    pub async fn token_to_eth_input(&self, role: &str, tokens_sold: U256, min_eth: U256, deadline: U256, recipient: Option<Address>) -> DeployResult<V1Receipt> {
+        check_amount("tokens_sold", tokens_sold)?;
+        check_amount("min_eth", min_eth)?;
+        self.check_deadline(deadline).await?;
+        self.check_recipient(recipient)?;
+        let mut params: Vec<Token> = vec![Token::Uint(tokens_sold), Token::Uint(min_eth), Token::Uint(deadline)];
+        let method: &str =
+            match recipient {
+                Some(recipient) => { params.push(Token::Address(recipient)); "tokenToEthTransferInput" },
+                None => "tokenToEthSwapInput",
+            };
+        self.trade(role, "v1_token_to_eth_input", method, params, None).await
+    }
+    pub async fn token_to_eth_output(&self, role: &str, eth_bought: U256, max_tokens: U256, deadline: U256, recipient: Option<Address>) -> DeployResult<V1Receipt> {
+        check_amount("eth_bought", eth_bought)?;
+        check_amount("max_tokens", max_tokens)?;
+        self.check_deadline(deadline).await?;
+        self.check_recipient(recipient)?;
+        let mut params: Vec<Token> = vec![Token::Uint(eth_bought), Token::Uint(max_tokens), Token::Uint(deadline)];
+        let method: &str =
+            match recipient {
+                Some(recipient) => { params.push(Token::Address(recipient)); "tokenToEthTransferOutput" },
+                None => "tokenToEthSwapOutput",
+            };
+        self.trade(role, "v1_token_to_eth_output", method, params, None).await
+    }
+    pub async fn token_to_token_input(&self, role: &str, tokens_sold: U256, min_tokens_bought: U256, min_eth_bought: U256, deadline: U256, recipient: Option<Address>, token: Address) -> DeployResult<V1Receipt> {
+        self.check_output_token(token).await?;
+        self.token_to_other_input(role, ("v1_token_to_token_input", "tokenToTokenSwapInput", "tokenToTokenTransferInput"), tokens_sold, min_tokens_bought, min_eth_bought, deadline, recipient, token).await
+    }
+    pub async fn token_to_token_output(&self, role: &str, tokens_bought: U256, max_tokens_sold: U256, max_eth_sold: U256, deadline: U256, recipient: Option<Address>, token: Address) -> DeployResult<V1Receipt> {
+        self.check_output_token(token).await?;
+        self.token_to_other_output(role, ("v1_token_to_token_output", "tokenToTokenSwapOutput", "tokenToTokenTransferOutput"), tokens_bought, max_tokens_sold, max_eth_sold, deadline, recipient, token).await
+    }
+    pub async fn token_to_exchange_input(&self, role: &str, tokens_sold: U256, min_tokens_bought: U256, min_eth_bought: U256, deadline: U256, recipient: Option<Address>, exchange: Address) -> DeployResult<V1Receipt> {
+        self.check_output_exchange(exchange)?;
+        self.token_to_other_input(role, ("v1_token_to_exchange_input", "tokenToExchangeSwapInput", "tokenToExchangeTransferInput"), tokens_sold, min_tokens_bought, min_eth_bought, deadline, recipient, exchange).await
+    }
+    pub async fn token_to_exchange_output(&self, role: &str, tokens_bought: U256, max_tokens_sold: U256, max_eth_sold: U256, deadline: U256, recipient: Option<Address>, exchange: Address) -> DeployResult<V1Receipt> {
+        self.check_output_exchange(exchange)?;
+        self.token_to_other_output(role, ("v1_token_to_exchange_output", "tokenToExchangeSwapOutput", "tokenToExchangeTransferOutput"), tokens_bought, max_tokens_sold, max_eth_sold, deadline, recipient, exchange).await
+    }
+    // tokenToToken and tokenToExchange take the same arguments, the last one being the
+    // output token or its exchange. `methods` is (operation, swap method, transfer method).
+    #[allow(clippy::too_many_arguments)]
+    async fn token_to_other_input(&self, role: &str, methods: (&str, &str, &str), tokens_sold: U256, min_tokens_bought: U256, min_eth_bought: U256, deadline: U256, recipient: Option<Address>, output: Address) -> DeployResult<V1Receipt> {
+        check_amount("tokens_sold", tokens_sold)?;
+        check_amount("min_tokens_bought", min_tokens_bought)?;
+        check_amount("min_eth_bought", min_eth_bought)?;
+        self.check_deadline(deadline).await?;
+        self.check_recipient(recipient)?;
+        let mut params: Vec<Token> = vec![Token::Uint(tokens_sold), Token::Uint(min_tokens_bought), Token::Uint(min_eth_bought), Token::Uint(deadline)];
+        let (operation, swap, transfer) = methods;
+        let method: &str =
+            match recipient {
+                Some(recipient) => { params.push(Token::Address(recipient)); transfer },
+                None => swap,
+            };
+        params.push(Token::Address(output));
+        self.trade(role, operation, method, params, None).await
+    }
+    #[allow(clippy::too_many_arguments)]
+    async fn token_to_other_output(&self, role: &str, methods: (&str, &str, &str), tokens_bought: U256, max_tokens_sold: U256, max_eth_sold: U256, deadline: U256, recipient: Option<Address>, output: Address) -> DeployResult<V1Receipt> {
+        check_amount("tokens_bought", tokens_bought)?;
+        check_amount("max_tokens_sold", max_tokens_sold)?;
+        check_amount("max_eth_sold", max_eth_sold)?;
+        self.check_deadline(deadline).await?;
+        self.check_recipient(recipient)?;
+        let mut params: Vec<Token> = vec![Token::Uint(tokens_bought), Token::Uint(max_tokens_sold), Token::Uint(max_eth_sold), Token::Uint(deadline)];
+        let (operation, swap, transfer) = methods;
+        let method: &str =
+            match recipient {
+                Some(recipient) => { params.push(Token::Address(recipient)); transfer },
+                None => swap,
+            };
+        params.push(Token::Address(output));
+        self.trade(role, operation, method, params, None).await
+    }
+}
@@ -0,0 +1,167 @@
+use web3::types::{ Address, Bytes };
+
+use web3_tools::AsEip55;
+
+use crate::artifact::{ Artifact, ArtifactRegistry };
+use crate::address_book::AddressBook;
+use crate::manifest::{ Manifest, StepAction };
+use crate::error::{ DeployError, DeployResult };
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CodeState {
+    Missing,
+    Matches,
+    Differs,
+    Unverifiable,
+}
+
+// Zeroes the immutable and library ranges in both the on-chain code and the artifact's
+// runtime bytecode. Returns None if the lengths already disagree.
+fn masked(code: &[u8], artifact: &Artifact, mut expected: Vec<u8>) -> Option<(Vec<u8>,Vec<u8>)> {
+
+    if expected.len() != code.len() {
+        return None;
+    }
+
+    let mut actual: Vec<u8> = code.to_vec();
+    for range in artifact.masked_ranges().iter() {
+        let (start, end) = (range.start, range.start + range.length);
+        if end > actual.len() {
+            return None;
+        }
+        actual[start..end].iter_mut().for_each(|b| *b = 0);
+        expected[start..end].iter_mut().for_each(|b| *b = 0);
+    }
+    Some((actual, expected))
+}
+
+pub fn compare_runtime_code(code: &[u8], artifact: &Artifact) -> CodeState {
+
+    if code.is_empty() {
+        return CodeState::Missing;
+    }
+    let expected: Vec<u8> =
+        match artifact.runtime_bytecode() {
+            Some(runtime) => runtime,
+            None => return CodeState::Unverifiable,
+        };
+
+    match masked(code, artifact, expected) {
+        Some((actual, expected)) if actual == expected => CodeState::Matches,
+        _ => CodeState::Differs,
+    }
+}
+
+pub fn first_difference(code: &[u8], artifact: &Artifact) -> Option<usize> {
+    let expected: Vec<u8> = artifact.runtime_bytecode()?;
+    match masked(code, artifact, expected.clone()) {
+        Some((actual, expected)) => actual.iter().zip(expected.iter()).position(|(a, e)| a != e),
+        None => Some(code.len().min(expected.len())),
+    }
+}
+
+pub async fn verify_deployment(web3: &web3::Web3<web3::transports::Http>, manifest: &Manifest, artifacts: &ArtifactRegistry, address_book: &AddressBook) -> DeployResult<bool> {
+
+    println!("{:<28} {:<44} {:>6}  {}", "Contract", "Address", "Size", "Result");
+
+    // Libraries the deployment added on its own are checked like any other contract.
+    let mut passed: bool = true;
+    for step in manifest.with_libraries(artifacts)?.iter() {
+        let artifact_name: &str =
+            match &step.action {
+                StepAction::Deploy { artifact, .. } => artifact,
+                StepAction::Call { .. } => continue,
+            };
+        let address: Address =
+            match address_book.address(&step.name) {
+                Some(address) => address,
+                None => {
+                    println!("{:<28} {:<44} {:>6}  FAIL (not in address book)", step.title, "-", "-");
+                    passed = false;
+                    continue;
+                },
+            };
+        let artifact: &Artifact = artifacts.get(artifact_name).map_err(|e| e.at_step(&step.name))?;
+
+        let code: Bytes = web3.eth().code(address, None).await.map_err(|e| DeployError::rpc(e).at_step(&step.name).for_contract(artifact_name))?;
+
+        let result: String =
+            match compare_runtime_code(&code.0, artifact) {
+                CodeState::Matches => "pass".to_string(),
+                CodeState::Unverifiable => format!("skipped ({} artifact has no runtime bytecode)", artifact.format),
+                CodeState::Missing => {
+                    passed = false;
+                    "FAIL (no code)".to_string()
+                },
+                CodeState::Differs => {
+                    passed = false;
+                    let expected_len: usize = artifact.runtime_bytecode().map(|r| r.len()).unwrap_or(0);
+                    match first_difference(&code.0, artifact) {
+                        Some(offset) if code.0.len() == expected_len => format!("FAIL (differs at byte {})", offset),
+                        _ => format!("FAIL (size {} expected {})", code.0.len(), expected_len),
+                    }
+                },
+            };
+        println!("{:<28} {:<44} {:>6}  {}", step.title, address.as_eip55(), code.0.len(), result);
+    }
+
+    Ok(passed)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+    use crate::artifact::tests::artifact;
+
+    // PUSH32 <immutable> PUSH20 <library> STOP
+    fn runtime_artifact() -> Artifact {
+        artifact(json!({
+            "abi": [],
+            "metadata": "{}",
+            "evm": {
+                "bytecode": { "object": "6080", "linkReferences": {} },
+                "deployedBytecode": {
+                    "object": format!("7f{}73{}00", "00".repeat(32), "__$0123456789abcdef0123456789abcdef01$__"),
+                    "linkReferences": { "contracts/Math.sol": { "Math": [ { "start": 34, "length": 20 } ] } },
+                    "immutableReferences": { "7": [ { "start": 1, "length": 32 } ] },
+                },
+            },
+        }))
+    }
+
+    fn deployed(immutable: u8, library: u8) -> Vec<u8> {
+        let mut code: Vec<u8> = vec![0x7f];
+        code.extend(vec![immutable; 32]);
+        code.push(0x73);
+        code.extend(vec![library; 20]);
+        code.push(0x00);
+        code
+    }
+
+    #[test]
+    fn matches_code_that_differs_only_in_masked_ranges() {
+        let artifact: Artifact = runtime_artifact();
+        assert_eq!(compare_runtime_code(&deployed(0x11, 0x22), &artifact), CodeState::Matches);
+        assert_eq!(first_difference(&deployed(0x11, 0x22), &artifact), None);
+    }
+
+    #[test]
+    fn reports_differences_outside_masked_ranges() {
+        let artifact: Artifact = runtime_artifact();
+        let mut code: Vec<u8> = deployed(0x11, 0x22);
+        code[33] = 0x72;
+        assert_eq!(compare_runtime_code(&code, &artifact), CodeState::Differs);
+        assert_eq!(first_difference(&code, &artifact), Some(33));
+
+        code.push(0x00);
+        assert_eq!(compare_runtime_code(&code, &artifact), CodeState::Differs);
+    }
+
+    #[test]
+    fn classifies_missing_and_unverifiable_code() {
+        assert_eq!(compare_runtime_code(&[], &runtime_artifact()), CodeState::Missing);
+        let truffle: Artifact = artifact(json!({ "abi": [], "bytecode": "0x6080", "deployedBytecode": "0x" }));
+        assert_eq!(compare_runtime_code(&[0x60, 0x80], &truffle), CodeState::Unverifiable);
+    }
+}